@@ -1,12 +1,13 @@
-use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
-use interval_tree::IntervalTreeNode;
-use interval_tree::*;
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use kd_interval_tree::IntervalTreeNode;
+use kd_interval_tree::*;
 use rand::distributions::Uniform;
 use rand::rngs::StdRng;
 use rand::{Rng, SeedableRng};
 
 pub struct Point2d(f64, f64);
 
+#[derive(Clone)]
 pub struct Rectangle {
     xmin: f64,
     xmax: f64,
@@ -120,5 +121,151 @@ fn access_n_rect(c: &mut Criterion) {
     group.finish();
 }
 
-criterion_group!(benches, access_n_point, access_n_rect);
+// Large queries go through both children of most nodes, which is where the intermediate results
+// used to be allocated
+fn access_n_large_rect(c: &mut Criterion) {
+    let mut group = c.benchmark_group("access_n_large_rect");
+    for size in (0..7).map(|n| 10u64.pow(n)) {
+        group.throughput(Throughput::Elements(size));
+        let mut r = RectRng::new();
+        let tree = r.random_tree(size);
+        let rect = Rectangle::new(-50.0, 50.0, -50.0, 50.0);
+        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, _| {
+            b.iter(|| tree.range_search(&rect));
+        });
+    }
+    group.finish();
+}
+
+// Queries overlapping with many split values, but with few intervals (here, none on y): the
+// bounding boxes of the nodes prune the subtrees the split values alone cannot
+fn access_n_selective(c: &mut Criterion) {
+    let mut group = c.benchmark_group("access_n_selective");
+    for size in (0..7).map(|n| 10u64.pow(n)) {
+        group.throughput(Throughput::Elements(size));
+        let mut r = RectRng::new();
+        let tree = r.random_tree(size);
+        let rect = Rectangle::new(-50.0, 50.0, 1000.0, 1001.0);
+        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, _| {
+            b.iter(|| tree.range_search(&rect));
+        });
+    }
+    group.finish();
+}
+
+// Leaves of a tree of limited depth, where the overlap tests dominate
+fn access_n_large_leaf(c: &mut Criterion) {
+    let mut group = c.benchmark_group("access_n_large_leaf");
+    for size in (2..6).map(|n| 10u64.pow(n)) {
+        group.throughput(Throughput::Elements(size));
+        let mut r = RectRng::new();
+        let intervals = (0..size).map(|_| r.random_rect()).collect::<Vec<_>>();
+        let tree = IntervalTreeNode::<_, 2>::from_intervals_max_depth(intervals, 2);
+        let rect = Rectangle::new(-50.0, 50.0, -50.0, 50.0);
+        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, _| {
+            b.iter(|| tree.range_search(&rect));
+        });
+    }
+    group.finish();
+}
+
+// Building then querying a tree, most of whose leaves hold one or two intervals: compare with
+// and without the smallvec feature
+fn build_and_query_n(c: &mut Criterion) {
+    let mut group = c.benchmark_group("build_and_query_n");
+    for size in (1..6).map(|n| 10u64.pow(n)) {
+        group.throughput(Throughput::Elements(size));
+        let mut r = RectRng::new();
+        let intervals = (0..size).map(|_| r.random_rect()).collect::<Vec<_>>();
+        let points = (0..100).map(|_| r.random_point()).collect::<Vec<_>>();
+        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, _| {
+            b.iter_batched(
+                || intervals.clone(),
+                |intervals| {
+                    let tree = IntervalTreeNode::<_, 2>::from_intervals(intervals);
+                    points
+                        .iter()
+                        .map(|p| tree.range_search(p).len())
+                        .sum::<usize>()
+                },
+                BatchSize::LargeInput,
+            );
+        });
+    }
+    group.finish();
+}
+
+// Allocating the partitions of each level with their exact capacity did not measurably change
+// from_intervals at 10^6 intervals: medians of 15 builds between 264 and 308 ms before, between
+// 270 and 314 ms after (three runs each, measured on the same machine)
+fn build_n(c: &mut Criterion) {
+    let mut group = c.benchmark_group("build_n");
+    for size in (0..7).map(|n| 10u64.pow(n)) {
+        group.throughput(Throughput::Elements(size));
+        let mut r = RectRng::new();
+        let intervals = (0..size).map(|_| r.random_rect()).collect::<Vec<_>>();
+        let mut sorted = (0..size).map(|_| r.random_rect()).collect::<Vec<_>>();
+        sorted.sort_by(|a, b| a.avg_at(0).partial_cmp(&b.avg_at(0)).unwrap());
+        group.bench_with_input(BenchmarkId::new("from_intervals", size), &size, |b, _| {
+            b.iter_batched(
+                || intervals.clone(),
+                IntervalTreeNode::<_, 2>::from_intervals,
+                BatchSize::LargeInput,
+            );
+        });
+        let orders: [Vec<usize>; 2] = std::array::from_fn(|k| {
+            let mut order = (0..intervals.len()).collect::<Vec<_>>();
+            order.sort_by(|&a, &b| {
+                intervals[a]
+                    .avg_at(k)
+                    .partial_cmp(&intervals[b].avg_at(k))
+                    .unwrap()
+            });
+            order
+        });
+        group.bench_with_input(
+            BenchmarkId::new("from_sorted_by_dimension", size),
+            &size,
+            |b, _| {
+                b.iter_batched(
+                    || (intervals.clone(), orders.clone()),
+                    |(intervals, orders)| {
+                        IntervalTreeNode::<_, 2>::from_sorted_by_dimension(intervals, orders)
+                    },
+                    BatchSize::LargeInput,
+                );
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("from_intervals_unchecked", size),
+            &size,
+            |b, _| {
+                b.iter_batched(
+                    || intervals.clone(),
+                    IntervalTreeNode::<_, 2>::from_intervals_unchecked,
+                    BatchSize::LargeInput,
+                );
+            },
+        );
+        group.bench_with_input(BenchmarkId::new("from_presorted", size), &size, |b, _| {
+            b.iter_batched(
+                || sorted.clone(),
+                IntervalTreeNode::<_, 2>::from_presorted,
+                BatchSize::LargeInput,
+            );
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    access_n_point,
+    access_n_rect,
+    access_n_large_rect,
+    access_n_large_leaf,
+    access_n_selective,
+    build_n,
+    build_and_query_n
+);
 criterion_main!(benches);
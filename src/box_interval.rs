@@ -0,0 +1,67 @@
+use crate::{Interval, Scalar};
+
+/// Plain interval given by its (min, max) bounds on each dimension, e.g. as returned by
+/// Interval::translated or Interval::scaled, to be directly used as a query.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoxInterval<S, const K: usize>(pub [(S, S); K]);
+
+impl<S: Scalar, const K: usize> Interval<K> for BoxInterval<S, K> {
+    type Scalar = S;
+    fn min_at(&self, k: usize) -> S {
+        self.0[k].0
+    }
+
+    fn max_at(&self, k: usize) -> S {
+        self.0[k].1
+    }
+}
+
+impl<S, const K: usize> From<[(S, S); K]> for BoxInterval<S, K> {
+    fn from(bounds: [(S, S); K]) -> BoxInterval<S, K> {
+        BoxInterval(bounds)
+    }
+}
+
+/// Projection of an interval on M of its axes, as built from Interval::project.
+pub type ProjectedInterval<S, const M: usize> = BoxInterval<S, M>;
+
+/// Same as BoxInterval, borrowing separate mins and maxs slices, for any dimension (including
+/// dynamically-sized ones).
+pub(crate) struct SliceBounds<'a, S> {
+    pub(crate) mins: &'a [S],
+    pub(crate) maxs: &'a [S],
+}
+
+impl<S: Scalar, const K: usize> Interval<K> for SliceBounds<'_, S> {
+    type Scalar = S;
+    fn min_at(&self, k: usize) -> S {
+        self.mins[k]
+    }
+
+    fn max_at(&self, k: usize) -> S {
+        self.maxs[k]
+    }
+
+    fn dimension(&self) -> usize {
+        self.mins.len()
+    }
+}
+
+/// Same as BoxInterval, borrowing the (min, max) bounds, for any dimension (including
+/// dynamically-sized ones).
+pub(crate) struct PairBounds<'a, S>(pub(crate) &'a [(S, S)]);
+
+impl<S: Scalar, const K: usize> Interval<K> for PairBounds<'_, S> {
+    type Scalar = S;
+    fn min_at(&self, k: usize) -> S {
+        self.0[k].0
+    }
+
+    fn max_at(&self, k: usize) -> S {
+        self.0[k].1
+    }
+
+    fn dimension(&self) -> usize {
+        self.0.len()
+    }
+}
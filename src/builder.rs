@@ -0,0 +1,45 @@
+use crate::{Interval, IntervalTreeNode};
+
+/// Buffers intervals pushed one by one, until the tree is built from all of them by finalize.
+pub struct IntervalTreeBuilder<I: Interval<K>, const K: usize> {
+    intervals: Vec<I>,
+}
+
+impl<I: Interval<K>, const K: usize> IntervalTreeBuilder<I, K> {
+    pub fn new() -> IntervalTreeBuilder<I, K> {
+        IntervalTreeBuilder {
+            intervals: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, interval: I) {
+        self.intervals.push(interval);
+    }
+
+    /// The number of intervals pushed so far.
+    pub fn len(&self) -> usize {
+        self.intervals.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.intervals.is_empty()
+    }
+
+    /// Builds the tree from the pushed intervals (see IntervalTreeNode::from_intervals), or
+    /// returns None if none was pushed, trees not being buildable from no interval.
+    pub fn finalize(self) -> Option<IntervalTreeNode<I, K>> {
+        (!self.intervals.is_empty()).then(|| IntervalTreeNode::from_intervals(self.intervals))
+    }
+}
+
+impl<I: Interval<K>, const K: usize> Default for IntervalTreeBuilder<I, K> {
+    fn default() -> Self {
+        IntervalTreeBuilder::new()
+    }
+}
+
+impl<I: Interval<K>, const K: usize> Extend<I> for IntervalTreeBuilder<I, K> {
+    fn extend<T: IntoIterator<Item = I>>(&mut self, intervals: T) {
+        self.intervals.extend(intervals);
+    }
+}
@@ -0,0 +1,45 @@
+use crate::iter::IntervalTreeIterator;
+use crate::{Interval, IntervalTreeNode};
+
+/// View of two trees queried as if they were a single one, e.g. for layered datasets, without
+/// rebuilding a tree from both of them (see IntervalTreeNode::merge).
+pub struct ChainedTrees<'a, I: Interval<K>, const K: usize> {
+    first: &'a IntervalTreeNode<I, K>,
+    second: &'a IntervalTreeNode<I, K>,
+}
+
+impl<'a, I: Interval<K>, const K: usize> ChainedTrees<'a, I, K> {
+    /// Same as IntervalTreeNode::range_search: the matches of the first tree, then the ones of
+    /// the second tree.
+    pub fn range_search<II: Interval<K, Scalar = I::Scalar>>(&self, x: &II) -> Vec<&'a I> {
+        let mut out = self.first.range_search(x);
+        out.extend(self.second.range_search(x));
+        out
+    }
+
+    /// Returns an iterator over all intervals of the first tree, then of the second tree.
+    pub fn iter(
+        &self,
+    ) -> std::iter::Chain<IntervalTreeIterator<'a, I, K>, IntervalTreeIterator<'a, I, K>> {
+        self.first.iter().chain(self.second.iter())
+    }
+
+    /// The number of intervals in both trees.
+    pub fn len(&self) -> usize {
+        self.first.len() + self.second.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.first.is_empty() && self.second.is_empty()
+    }
+}
+
+impl<I: Interval<K>, const K: usize> IntervalTreeNode<I, K> {
+    /// Returns a view querying self, then other.
+    pub fn chain<'a>(&'a self, other: &'a IntervalTreeNode<I, K>) -> ChainedTrees<'a, I, K> {
+        ChainedTrees {
+            first: self,
+            second: other,
+        }
+    }
+}
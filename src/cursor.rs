@@ -0,0 +1,64 @@
+use crate::{Interval, IntervalTreeNode, NodeContent};
+
+/// Read-only handle for navigating the tree manually, e.g. for visualizing or debugging it.
+/// Cursors are cheap to copy: every move returns a new cursor, leaving self untouched.
+pub struct Cursor<'a, I: Interval<K>, const K: usize> {
+    node: &'a IntervalTreeNode<I, K>,
+}
+
+impl<I: Interval<K>, const K: usize> Clone for Cursor<'_, I, K> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<I: Interval<K>, const K: usize> Copy for Cursor<'_, I, K> {}
+
+impl<'a, I: Interval<K>, const K: usize> Cursor<'a, I, K> {
+    /// Moves to the node containing the intervals lower than the center value, if any.
+    pub fn go_left(&self) -> Option<Cursor<'a, I, K>> {
+        self.node.lt_nodes.as_deref().map(|node| Cursor { node })
+    }
+
+    /// Moves to the node containing the intervals greater than the center value, if any.
+    pub fn go_right(&self) -> Option<Cursor<'a, I, K>> {
+        self.node.gt_nodes.as_deref().map(|node| Cursor { node })
+    }
+
+    /// Moves to the root of the subtree splitting the intervals straddling the center value on the
+    /// next dimension.
+    /// Returns the intervals of the leaf as an error if the center is a leaf.
+    pub fn go_center(&self) -> Result<Cursor<'a, I, K>, &'a [I]> {
+        match &self.node.center {
+            NodeContent::Subtree(node) => Ok(Cursor { node }),
+            NodeContent::Leaf(intervals) => Err(intervals),
+        }
+    }
+
+    /// The value this node splits at.
+    pub fn center_value(&self) -> I::Scalar {
+        self.node.center_val
+    }
+
+    /// The dimension this node splits on.
+    pub fn split_dimension(&self) -> usize {
+        self.node.k
+    }
+
+    /// The intervals straddling the center value, if the center is a leaf.
+    pub fn leaf_intervals(&self) -> Option<&'a [I]> {
+        self.go_center().err()
+    }
+
+    /// The node self points to.
+    pub fn node(&self) -> &'a IntervalTreeNode<I, K> {
+        self.node
+    }
+}
+
+impl<I: Interval<K>, const K: usize> IntervalTreeNode<I, K> {
+    /// Returns a cursor pointing to the root of the tree.
+    pub fn cursor(&self) -> Cursor<'_, I, K> {
+        Cursor { node: self }
+    }
+}
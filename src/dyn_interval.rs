@@ -0,0 +1,88 @@
+use crate::interval_tree::AxisBounds;
+use crate::{Interval, Scalar};
+use std::cmp::Ordering;
+
+/// Object-safe subset of Interval, so that intervals of different types can be stored in the same
+/// tree, as Box<dyn IntervalCore<K, Scalar = S>> (which implements Interval).
+/// Every Interval implements it, e.g. Box::new(rect) as Box<dyn IntervalCore<2, Scalar = f64>>.
+/// The methods are named differently from the ones of Interval, so that both traits can be in
+/// scope without ambiguity. Besides the bounds and dimension, avg_at, overlaps_at and cmp_at are
+/// forwarded (e.g. for boxed Periodic's), the other interval of overlaps_at being only given by
+/// its bounds on the compared dimension. The other methods of Interval use their default
+/// implementation on the boxed intervals.
+pub trait IntervalCore<const K: usize> {
+    type Scalar: Scalar;
+
+    /// Same as Interval::min_at.
+    fn dyn_min_at(&self, k: usize) -> Self::Scalar;
+
+    /// Same as Interval::max_at.
+    fn dyn_max_at(&self, k: usize) -> Self::Scalar;
+
+    /// Same as Interval::dimension.
+    fn dyn_dimension(&self) -> usize;
+
+    /// Same as Interval::avg_at.
+    fn dyn_avg_at(&self, k: usize) -> Self::Scalar;
+
+    /// Same as Interval::overlaps_at, given the (min, max) bounds of the other interval on the
+    /// kth dimension.
+    fn dyn_overlaps_at(&self, k: usize, o: (Self::Scalar, Self::Scalar)) -> bool;
+
+    /// Same as Interval::cmp_at.
+    fn dyn_cmp_at(&self, k: usize, s: Self::Scalar) -> Ordering;
+}
+
+impl<const K: usize, I: Interval<K>> IntervalCore<K> for I {
+    type Scalar = I::Scalar;
+    fn dyn_min_at(&self, k: usize) -> Self::Scalar {
+        self.min_at(k)
+    }
+
+    fn dyn_max_at(&self, k: usize) -> Self::Scalar {
+        self.max_at(k)
+    }
+
+    fn dyn_dimension(&self) -> usize {
+        self.dimension()
+    }
+
+    fn dyn_avg_at(&self, k: usize) -> Self::Scalar {
+        self.avg_at(k)
+    }
+
+    fn dyn_overlaps_at(&self, k: usize, (lo, hi): (Self::Scalar, Self::Scalar)) -> bool {
+        self.overlaps_at(k, &AxisBounds { axis: k, lo, hi })
+    }
+
+    fn dyn_cmp_at(&self, k: usize, s: Self::Scalar) -> Ordering {
+        self.cmp_at(k, s)
+    }
+}
+
+impl<const K: usize, S: Scalar> Interval<K> for Box<dyn IntervalCore<K, Scalar = S> + '_> {
+    type Scalar = S;
+    fn min_at(&self, k: usize) -> S {
+        (**self).dyn_min_at(k)
+    }
+
+    fn max_at(&self, k: usize) -> S {
+        (**self).dyn_max_at(k)
+    }
+
+    fn dimension(&self) -> usize {
+        (**self).dyn_dimension()
+    }
+
+    fn avg_at(&self, k: usize) -> S {
+        (**self).dyn_avg_at(k)
+    }
+
+    fn overlaps_at<II: Interval<K, Scalar = S>>(&self, k: usize, o: &II) -> bool {
+        (**self).dyn_overlaps_at(k, (o.min_at(k), o.max_at(k)))
+    }
+
+    fn cmp_at(&self, k: usize, s: S) -> Ordering {
+        (**self).dyn_cmp_at(k, s)
+    }
+}
@@ -0,0 +1,34 @@
+use crate::{Interval, Point, Scalar};
+use geo_types::{Coord, CoordNum, Rect};
+
+/// geo::Rect's can be stored in trees as is, e.g. IntervalTreeNode::<Rect<f64>, 2>.
+impl<T: CoordNum + Scalar> Interval<2> for Rect<T> {
+    type Scalar = T;
+    fn min_at(&self, k: usize) -> T {
+        match k {
+            0 => self.min().x,
+            1 => self.min().y,
+            _ => panic!("Index {} out of bounds for a rectangle of dimension 2", k),
+        }
+    }
+
+    fn max_at(&self, k: usize) -> T {
+        match k {
+            0 => self.max().x,
+            1 => self.max().y,
+            _ => panic!("Index {} out of bounds for a rectangle of dimension 2", k),
+        }
+    }
+}
+
+/// geo::Coord's can be used to query trees of any 2D interval (e.g. geo::Rect's).
+impl<T: CoordNum + Scalar> Point<2> for Coord<T> {
+    type Scalar = T;
+    fn value(&self, k: usize) -> T {
+        match k {
+            0 => self.x,
+            1 => self.y,
+            _ => panic!("Index {} out of bounds for a point of dimension 2", k),
+        }
+    }
+}
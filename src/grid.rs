@@ -0,0 +1,137 @@
+use crate::Interval;
+use num_traits::ToPrimitive;
+
+/// Alternative to the median-split tree, bucketing the intervals in a regular grid w.r.t their
+/// centers.
+/// Building a grid is a single linear pass (no sorting nor partitioning), and queries only scan
+/// the cells that may contain overlapping intervals. Grids thus beat the median tree for
+/// uniformly distributed intervals of similar sizes. They degrade with clustered data (most
+/// intervals end up in a few cells) and with a few very large intervals (every query has to
+/// scan the cells any interval centered there could reach), where the median tree should be
+/// preferred.
+pub struct IntervalGrid<I: Interval<K>, const K: usize> {
+    cells: Vec<Vec<I>>,
+    cells_per_dim: usize,
+    /// For each dimension, the lowest center of the intervals.
+    origin: Vec<f64>,
+    /// For each dimension, the size of the cells.
+    cell_size: Vec<f64>,
+    /// For each dimension, the largest distance between the center and a bound of any interval.
+    reach: Vec<f64>,
+}
+
+fn to_f64<S: ToPrimitive>(s: S) -> f64 {
+    s.to_f64()
+        .expect("Scalars should be convertible to f64 when using a grid!")
+}
+
+impl<I: Interval<K>, const K: usize> IntervalGrid<I, K>
+where
+    I::Scalar: ToPrimitive,
+{
+    /// Creates an IntervalGrid given a collection of intervals, with cells_per_dim cells on each
+    /// dimension (i.e. cells_per_dim^dimension cells in total).
+    pub fn from_intervals_grid(intervals: Vec<I>, cells_per_dim: usize) -> IntervalGrid<I, K> {
+        assert!(
+            cells_per_dim > 0,
+            "A grid needs at least one cell per dimension!"
+        );
+        let dimension = intervals.first().map_or(0, |i| i.dimension());
+        let mut origin = vec![f64::INFINITY; dimension];
+        let mut end = vec![f64::NEG_INFINITY; dimension];
+        let mut reach = vec![0.0f64; dimension];
+        for i in &intervals {
+            assert!(
+                i.dimension() == dimension,
+                "Intervals need to have the same dimension when transformed into a grid!"
+            );
+            for k in 0..dimension {
+                let center = to_f64(i.avg_at(k));
+                origin[k] = origin[k].min(center);
+                end[k] = end[k].max(center);
+                reach[k] = reach[k]
+                    .max(center - to_f64(i.min_at(k)))
+                    .max(to_f64(i.max_at(k)) - center);
+            }
+        }
+        let cell_size = origin
+            .iter()
+            .zip(&end)
+            .map(|(o, e)| (e - o) / cells_per_dim as f64)
+            .collect();
+
+        let mut grid = IntervalGrid {
+            cells: Vec::new(),
+            cells_per_dim,
+            origin,
+            cell_size,
+            reach,
+        };
+        grid.cells
+            .resize_with(cells_per_dim.pow(dimension as u32), Vec::new);
+        for i in intervals {
+            let cell = (0..dimension).rev().fold(0, |cell, k| {
+                cell * cells_per_dim + grid.cell_at(k, to_f64(i.avg_at(k)))
+            });
+            grid.cells[cell].push(i);
+        }
+        grid
+    }
+
+    /// The index of the cell containing v on the kth dimension.
+    fn cell_at(&self, k: usize, v: f64) -> usize {
+        if self.cell_size[k] <= 0.0 {
+            return 0;
+        }
+        let cell = ((v - self.origin[k]) / self.cell_size[k]).floor();
+        // The highest center lies on the upper bound of the last cell
+        cell.clamp(0.0, (self.cells_per_dim - 1) as f64) as usize
+    }
+
+    /// Same as IntervalTreeNode::range_search: returns all the Interval's in the grid overlapping
+    /// with x.
+    pub fn range_search<II: Interval<K, Scalar = I::Scalar>>(&self, x: &II) -> Vec<&I> {
+        let mut out = Vec::new();
+        if self.is_empty() {
+            return out;
+        }
+        // The intervals overlapping x have their center within reach of it
+        let ranges = (0..self.origin.len())
+            .map(|k| {
+                let lo = to_f64(x.min_at(k)) - self.reach[k];
+                let hi = to_f64(x.max_at(k)) + self.reach[k];
+                self.cell_at(k, lo)..=self.cell_at(k, hi)
+            })
+            .collect::<Vec<_>>();
+        self.range_search_rec(&ranges, ranges.len(), 0, x, &mut out);
+        out
+    }
+
+    /// Scans all the cells within ranges on the dimensions lower than k, cell being the index of
+    /// the cell on the dimensions greater or equal to k.
+    fn range_search_rec<'a, II: Interval<K, Scalar = I::Scalar>>(
+        &'a self,
+        ranges: &[std::ops::RangeInclusive<usize>],
+        k: usize,
+        cell: usize,
+        x: &II,
+        out: &mut Vec<&'a I>,
+    ) {
+        if k == 0 {
+            out.extend(self.cells[cell].iter().filter(|i| i.overlaps(x)));
+            return;
+        }
+        for c in ranges[k - 1].clone() {
+            self.range_search_rec(ranges, k - 1, cell * self.cells_per_dim + c, x, out);
+        }
+    }
+
+    /// The number of intervals in the grid.
+    pub fn len(&self) -> usize {
+        self.cells.iter().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.iter().all(Vec::is_empty)
+    }
+}
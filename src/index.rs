@@ -0,0 +1,60 @@
+use crate::{Interval, Scalar};
+
+/// Sorted endpoints of a set of intervals, for each dimension.
+/// This is a much lighter structure than the tree, which only supports counting the intervals
+/// overlapping a range on a single dimension, in O(log(n)).
+pub struct IntervalIndex<S: Scalar> {
+    /// For each dimension, the sorted min_at values.
+    mins: Vec<Vec<S>>,
+    /// For each dimension, the sorted max_at values.
+    maxs: Vec<Vec<S>>,
+}
+
+impl<S: Scalar> IntervalIndex<S> {
+    /// Creates an IntervalIndex given a collection of intervals, e.g. a slice or tree.iter().
+    pub fn new<'a, const K: usize, I: Interval<K, Scalar = S> + 'a>(
+        intervals: impl IntoIterator<Item = &'a I>,
+    ) -> IntervalIndex<S> {
+        let mut mins: Vec<Vec<S>> = Vec::new();
+        let mut maxs: Vec<Vec<S>> = Vec::new();
+        for i in intervals {
+            if mins.is_empty() {
+                mins.resize(i.dimension(), Vec::new());
+                maxs.resize(i.dimension(), Vec::new());
+            }
+            assert!(
+                i.dimension() == mins.len(),
+                "Intervals need to have the same dimension when indexed!"
+            );
+            for k in 0..mins.len() {
+                mins[k].push(i.min_at(k));
+                maxs[k].push(i.max_at(k));
+            }
+        }
+        for v in mins.iter_mut().chain(maxs.iter_mut()) {
+            v.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        }
+        IntervalIndex { mins, maxs }
+    }
+
+    /// The number of indexed intervals.
+    pub fn len(&self) -> usize {
+        self.mins.first().map_or(0, Vec::len)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of intervals overlapping [lo, hi] on the kth dimension (0-indexed),
+    /// regardless of their other dimensions.
+    pub fn count_in_range(&self, k: usize, lo: S, hi: S) -> usize {
+        if self.is_empty() {
+            return 0;
+        }
+        // Every interval except the ones ending before lo, or starting after hi
+        let ending_before = self.maxs[k].partition_point(|&max| max < lo);
+        let starting_after = self.mins[k].len() - self.mins[k].partition_point(|&min| min <= hi);
+        self.len() - ending_before - starting_after
+    }
+}
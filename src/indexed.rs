@@ -0,0 +1,173 @@
+use crate::{Interval, IntervalTreeNode, Point};
+use std::cmp::Ordering;
+use std::ops::{Deref, DerefMut};
+
+/// An interval, along with its position in the input of IntervalTreeNode::from_intervals_indexed.
+/// Forwards the Interval implementation of the wrapped interval.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Indexed<I> {
+    interval: I,
+    index: usize,
+}
+
+impl<I> Indexed<I> {
+    pub(crate) fn new(interval: I, index: usize) -> Indexed<I> {
+        Indexed { interval, index }
+    }
+
+    /// The position of the interval in the input.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn interval(&self) -> &I {
+        &self.interval
+    }
+
+    pub fn into_inner(self) -> I {
+        self.interval
+    }
+}
+
+/// An interval, along with the (e.g. feature) id given to IntervalTreeNode::from_id_intervals.
+/// Forwards the Interval implementation of the wrapped interval.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WithId<I, Id> {
+    interval: I,
+    id: Id,
+}
+
+impl<I, Id: Copy> WithId<I, Id> {
+    pub fn new(interval: I, id: Id) -> WithId<I, Id> {
+        WithId { interval, id }
+    }
+
+    pub fn id(&self) -> Id {
+        self.id
+    }
+
+    pub fn interval(&self) -> &I {
+        &self.interval
+    }
+
+    pub fn into_inner(self) -> I {
+        self.interval
+    }
+}
+
+macro_rules! impl_interval_forwarding {
+    ($wrapper:ident<I $(, $param:ident)*>) => {
+        impl<const K: usize, I: Interval<K> $(, $param)*> Interval<K> for $wrapper<I $(, $param)*> {
+            type Scalar = I::Scalar;
+            fn min_at(&self, k: usize) -> Self::Scalar {
+                self.interval.min_at(k)
+            }
+
+            fn max_at(&self, k: usize) -> Self::Scalar {
+                self.interval.max_at(k)
+            }
+
+            fn avg_at(&self, k: usize) -> Self::Scalar {
+                self.interval.avg_at(k)
+            }
+
+            fn overlaps_at<II: Interval<K, Scalar = Self::Scalar>>(&self, k: usize, o: &II) -> bool {
+                self.interval.overlaps_at(k, o)
+            }
+
+            fn contains_point<P: Point<K, Scalar = Self::Scalar>>(&self, p: &P) -> bool {
+                self.interval.contains_point(p)
+            }
+
+            fn dimension(&self) -> usize {
+                self.interval.dimension()
+            }
+
+            fn cmp_at(&self, k: usize, s: Self::Scalar) -> Ordering {
+                self.interval.cmp_at(k, s)
+            }
+        }
+    };
+}
+
+impl_interval_forwarding!(Indexed<I>);
+impl_interval_forwarding!(WithId<I, Id>);
+
+impl<const K: usize, I: Interval<K>> IntervalTreeNode<I, K> {
+    /// Creates an IntervalTreeNode given a collection of intervals, each of them being wrapped
+    /// with its position in intervals. The results of the queries can thus be mapped back to the
+    /// input, even though the construction reorders it.
+    pub fn from_intervals_indexed(intervals: Vec<I>) -> IndexedIntervalTree<I, K> {
+        let input_len = intervals.len();
+        let tree = IntervalTreeNode::from_intervals(
+            intervals
+                .into_iter()
+                .enumerate()
+                .map(|(index, i)| Indexed::new(i, index))
+                .collect(),
+        );
+        IndexedIntervalTree { tree, input_len }
+    }
+
+    /// Creates an IntervalTreeNode given a collection of intervals along with their (e.g.
+    /// feature) ids, which are kept through the construction.
+    pub fn from_id_intervals<Id: Copy>(items: Vec<(I, Id)>) -> IntervalTreeNode<WithId<I, Id>, K> {
+        IntervalTreeNode::from_intervals(
+            items
+                .into_iter()
+                .map(|(i, id)| WithId::new(i, id))
+                .collect(),
+        )
+    }
+}
+
+impl<const K: usize, I: Interval<K>, Id: Copy> IntervalTreeNode<WithId<I, Id>, K> {
+    /// Same as range_search, only returning the ids of the matches.
+    pub fn range_search_ids<II: Interval<K, Scalar = I::Scalar>>(&self, x: &II) -> Vec<Id> {
+        self.range_search(x).into_iter().map(WithId::id).collect()
+    }
+}
+
+/// A tree built by IntervalTreeNode::from_intervals_indexed, which remembers the length of its
+/// input. Derefs to the underlying tree for querying (and e.g. retain'ing) it.
+#[derive(Clone)]
+pub struct IndexedIntervalTree<I: Interval<K>, const K: usize> {
+    tree: IntervalTreeNode<Indexed<I>, K>,
+    input_len: usize,
+}
+
+impl<I: Interval<K>, const K: usize> IndexedIntervalTree<I, K> {
+    /// The number of intervals the tree was built from, i.e. the upper bound of their indices.
+    pub fn input_len(&self) -> usize {
+        self.input_len
+    }
+
+    pub fn into_tree(self) -> IntervalTreeNode<Indexed<I>, K> {
+        self.tree
+    }
+
+    /// Same as range_search, returning whether each interval matched, in the order of the input
+    /// of from_intervals_indexed instead (e.g. to filter metadata stored alongside it).
+    /// The mask has one element per interval of the input (removed intervals being reported as
+    /// not matching), whatever the query.
+    pub fn range_search_mask<II: Interval<K, Scalar = I::Scalar>>(&self, x: &II) -> Vec<bool> {
+        let mut mask = vec![false; self.input_len];
+        for index in self.range_search(x).into_iter().map(Indexed::index) {
+            mask[index] = true;
+        }
+        mask
+    }
+}
+
+impl<I: Interval<K>, const K: usize> Deref for IndexedIntervalTree<I, K> {
+    type Target = IntervalTreeNode<Indexed<I>, K>;
+    fn deref(&self) -> &Self::Target {
+        &self.tree
+    }
+}
+
+impl<I: Interval<K>, const K: usize> DerefMut for IndexedIntervalTree<I, K> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.tree
+    }
+}
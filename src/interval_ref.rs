@@ -0,0 +1,86 @@
+use crate::{Interval, IntervalTreeNode, Point};
+use std::cmp::Ordering;
+use std::ops::Deref;
+
+/// A borrowed interval, as stored by IntervalTreeNode::from_refs.
+/// A blanket implementation of Interval for &I would conflict with the one for Point's (as
+/// downstream crates may implement Point for references), hence this wrapper, which forwards the
+/// Interval implementation of the borrowed interval and derefs to it.
+#[derive(Debug, PartialEq)]
+pub struct IntervalRef<'a, I>(pub &'a I);
+
+impl<I> Clone for IntervalRef<'_, I> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<I> Copy for IntervalRef<'_, I> {}
+
+impl<'a, I> IntervalRef<'a, I> {
+    /// The borrowed interval, with the lifetime of the input of the tree.
+    pub fn get(&self) -> &'a I {
+        self.0
+    }
+}
+
+impl<I> Deref for IntervalRef<'_, I> {
+    type Target = I;
+    fn deref(&self) -> &I {
+        self.0
+    }
+}
+
+impl<const K: usize, I: Interval<K>> Interval<K> for IntervalRef<'_, I> {
+    type Scalar = I::Scalar;
+    fn min_at(&self, k: usize) -> Self::Scalar {
+        self.0.min_at(k)
+    }
+
+    fn max_at(&self, k: usize) -> Self::Scalar {
+        self.0.max_at(k)
+    }
+
+    fn avg_at(&self, k: usize) -> Self::Scalar {
+        self.0.avg_at(k)
+    }
+
+    fn overlaps_at<II: Interval<K, Scalar = Self::Scalar>>(&self, k: usize, o: &II) -> bool {
+        self.0.overlaps_at(k, o)
+    }
+
+    fn contains_point<P: Point<K, Scalar = Self::Scalar>>(&self, p: &P) -> bool {
+        self.0.contains_point(p)
+    }
+
+    fn dimension(&self) -> usize {
+        self.0.dimension()
+    }
+
+    fn cmp_at(&self, k: usize, s: Self::Scalar) -> Ordering {
+        self.0.cmp_at(k, s)
+    }
+}
+
+impl<'a, const K: usize, I: Interval<K>> IntervalTreeNode<IntervalRef<'a, I>, K> {
+    /// Creates an IntervalTreeNode borrowing the given intervals instead of owning them, e.g. to
+    /// index a large collection kept elsewhere without moving or cloning it.
+    pub fn from_refs(intervals: &'a [I]) -> IntervalTreeNode<IntervalRef<'a, I>, K> {
+        IntervalTreeNode::from_intervals(intervals.iter().map(IntervalRef).collect())
+    }
+}
+
+impl<const K: usize, I: Interval<K>> IntervalTreeNode<I, K> {
+    /// Returns a (smaller) tree borrowing the intervals of self overlapping with region, to be
+    /// queried repeatedly within region: queries inside it return the same matches as on self.
+    /// Returns None if no interval overlaps with region.
+    pub fn subtree_for<II: Interval<K, Scalar = I::Scalar>>(
+        &self,
+        region: &II,
+    ) -> Option<IntervalTreeNode<IntervalRef<'_, I>, K>> {
+        let intervals = self.range_search(region);
+        (!intervals.is_empty()).then(|| {
+            IntervalTreeNode::from_intervals(intervals.into_iter().map(IntervalRef).collect())
+        })
+    }
+}
@@ -1,8 +1,17 @@
-use std::cmp::Ordering;
+use std::borrow::Cow;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeMap, BinaryHeap};
+use std::convert::Infallible;
+use std::ops::ControlFlow;
 
-use crate::iter::IntervalTreeIterator;
-use num_traits::{NumAssign, NumOps, One};
+use crate::box_interval::SliceBounds;
+use crate::iter::{DistanceIter, Intersecting, IntervalTreeIterator};
+use crate::visit::{Leaves, Visitor};
+use crate::{BoxInterval, Indexed};
+use num_traits::{NumAssign, NumOps, One, Zero};
 use std::cmp::PartialOrd;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
 
 /// Most scalar types should already implement these (refer to num_traits for details)
 pub trait Scalar: NumOps + NumAssign + PartialOrd + Copy + std::iter::Product {}
@@ -39,6 +48,63 @@ impl<const K: usize, P: Point<K>> Interval<K> for P {
     fn max_at(&self, k: usize) -> Self::Scalar {
         self.value(k)
     }
+
+    fn dimension(&self) -> usize {
+        Point::dimension(self)
+    }
+}
+
+/// Coordinate arrays are points (and thus intervals) as is, e.g. tree.range_search(&[1.0, 2.0]).
+impl<const K: usize, S: Scalar> Point<K> for [S; K] {
+    type Scalar = S;
+    fn value(&self, k: usize) -> S {
+        self[k]
+    }
+}
+
+// Tuples of scalars are points (and thus intervals) as well, e.g. tree.range_search(&(1.0, 2.0))
+macro_rules! impl_point_for_tuple {
+    ($k:literal; $($i:tt: $s:ident),+) => {
+        impl<S: Scalar> Point<$k> for ($($s,)+) {
+            type Scalar = S;
+            fn value(&self, k: usize) -> S {
+                match k {
+                    $($i => self.$i,)+
+                    _ => panic!("Index {} out of bounds for a point of dimension {}", k, $k),
+                }
+            }
+        }
+    };
+}
+
+impl_point_for_tuple!(1; 0: S);
+impl_point_for_tuple!(2; 0: S, 1: S);
+impl_point_for_tuple!(3; 0: S, 1: S, 2: S);
+impl_point_for_tuple!(4; 0: S, 1: S, 2: S, 3: S);
+
+/// Note that the bounds of all intervals are inclusive: a Range is thus handled as the closed
+/// interval [start, end], i.e. 0.0..1.0 overlaps with 1.0..2.0. Use RangeInclusive to make that
+/// explicit.
+impl<S: Scalar> Interval<1> for std::ops::Range<S> {
+    type Scalar = S;
+    fn min_at(&self, _k: usize) -> S {
+        self.start
+    }
+
+    fn max_at(&self, _k: usize) -> S {
+        self.end
+    }
+}
+
+impl<S: Scalar> Interval<1> for std::ops::RangeInclusive<S> {
+    type Scalar = S;
+    fn min_at(&self, _k: usize) -> S {
+        *self.start()
+    }
+
+    fn max_at(&self, _k: usize) -> S {
+        *self.end()
+    }
 }
 
 // To avoid adding an Ord bound on Scalar...
@@ -67,15 +133,40 @@ pub trait Interval<const K: usize> {
     type Scalar: Scalar;
 
     /// The minimum value of self on the kth dimension (0-indexed).
-
     fn min_at(&self, k: usize) -> Self::Scalar;
+
     /// The maximum value of self on the kth dimension (0-indexed).
     fn max_at(&self, k: usize) -> Self::Scalar;
 
     /// The average between min_at and max_at
+    /// Computed as min + (max - min) / 2, which cannot overflow for unsigned scalars (contrary to
+    /// (min + max) / 2). Integer averages are thus rounded towards min.
     fn avg_at(&self, k: usize) -> Self::Scalar {
         // TODO: there *has* to be a better way creating a 2...
-        (self.min_at(k) + self.max_at(k)) / (Self::Scalar::one() + Self::Scalar::one())
+        self.min_at(k)
+            + (self.max_at(k) - self.min_at(k)) / (Self::Scalar::one() + Self::Scalar::one())
+    }
+
+    /// The center of self, i.e. avg_at on every dimension.
+    /// Dynamically-sized intervals (K = 0) should use center_point_vec instead.
+    fn center_point(&self) -> [Self::Scalar; K] {
+        std::array::from_fn(|k| self.avg_at(k))
+    }
+
+    /// Same as center_point, for any interval (including dynamically-sized ones).
+    fn center_point_vec(&self) -> Vec<Self::Scalar> {
+        (0..self.dimension()).map(|k| self.avg_at(k)).collect()
+    }
+
+    /// Returns whether self has no extent on the kth dimension (0-indexed), i.e. whether
+    /// min_at(k) == max_at(k).
+    fn is_degenerate_at(&self, k: usize) -> bool {
+        self.min_at(k) == self.max_at(k)
+    }
+
+    /// Returns whether self is degenerate on every dimension, i.e. is a point.
+    fn is_point(&self) -> bool {
+        (0..self.dimension()).all(|k| self.is_degenerate_at(k))
     }
 
     /// Returns whether self overlaps with the given interval
@@ -86,10 +177,27 @@ pub trait Interval<const K: usize> {
     /// Returns whether self overlaps with the given interval at the specified dimension k
     /// (0-indexed)
     fn overlaps_at<I: Interval<K, Scalar = Self::Scalar>>(&self, k: usize, o: &I) -> bool {
-        debug_assert!(k < K);
+        debug_assert!(k < self.dimension());
         self.min_at(k) <= o.max_at(k) && o.min_at(k) <= self.max_at(k)
     }
 
+    /// Returns whether self overlaps with o up to a tolerance of eps, i.e. whether the gap between
+    /// them is at most eps on every dimension, e.g. to absorb the rounding noise of floating point
+    /// coordinates near the bounds. Same as overlaps for eps = 0.
+    fn overlaps_within<I: Interval<K, Scalar = Self::Scalar>>(
+        &self,
+        o: &I,
+        eps: Self::Scalar,
+    ) -> bool {
+        (0..self.dimension())
+            .all(|k| self.min_at(k) <= o.max_at(k) + eps && o.min_at(k) <= self.max_at(k) + eps)
+    }
+
+    /// Returns whether p lies inside self (bounds included) on all dimensions.
+    fn contains_point<P: Point<K, Scalar = Self::Scalar>>(&self, p: &P) -> bool {
+        (0..self.dimension()).all(|k| self.min_at(k) <= p.value(k) && p.value(k) <= self.max_at(k))
+    }
+
     /// For compile-time known dimensions, returns the dimension.
     /// For dynamically-sized objects, K should be set to 0 and this function overriden.
     fn dimension(&self) -> usize {
@@ -99,6 +207,7 @@ pub trait Interval<const K: usize> {
 
     /// Returns the volume of the overlapping space between two intervals, if they overlap. Returns
     /// None if they do not.
+    /// The overlap is checked before each subtraction, so this is safe for unsigned scalars.
     fn try_overlapping_volume<I: Interval<K, Scalar = Self::Scalar>>(
         &self,
         o: &I,
@@ -114,18 +223,111 @@ pub trait Interval<const K: usize> {
         Some(v)
     }
 
+    /// Returns the length of the overlap between two intervals on each dimension, if they
+    /// overlap. Returns None if they do not.
+    /// Only meaningful for compile-time known dimensions.
+    fn overlap_extents<I: Interval<K, Scalar = Self::Scalar>>(
+        &self,
+        o: &I,
+    ) -> Option<[Self::Scalar; K]> {
+        if !(0..K).all(|k| self.overlaps_at(k, o)) {
+            return None;
+        }
+        Some(std::array::from_fn(|k| {
+            min(self.max_at(k), o.max_at(k)) - max(self.min_at(k), o.min_at(k))
+        }))
+    }
+
+    /// Returns the bounds of self clamped to the given bounds (e.g. a clipping box) on each
+    /// dimension, if they overlap. Returns None if they do not.
+    /// Only meaningful for compile-time known dimensions.
+    fn clamp_bounds<I: Interval<K, Scalar = Self::Scalar>>(
+        &self,
+        bounds: &I,
+    ) -> Option<[(Self::Scalar, Self::Scalar); K]> {
+        if !(0..K).all(|k| self.overlaps_at(k, bounds)) {
+            return None;
+        }
+        Some(std::array::from_fn(|k| {
+            (
+                max(self.min_at(k), bounds.min_at(k)),
+                min(self.max_at(k), bounds.max_at(k)),
+            )
+        }))
+    }
+
     /// Returns the volume of the overlapping space between two intervals, assuming they overlap.
     /// The they do not, the result returned is undefined.
+    /// With unsigned scalars, non-overlapping intervals make the subtraction underflow (i.e. panic
+    /// in debug builds): use try_overlapping_volume instead when unsure.
     fn overlapping_volume<I: Interval<K, Scalar = Self::Scalar>>(&self, o: &I) -> Self::Scalar {
         (0..self.dimension())
             .map(|k| min(self.max_at(k), o.max_at(k)) - max(self.min_at(k), o.min_at(k)))
             .product()
     }
 
+    /// Returns the volume of self, i.e. the product of its extents on every dimension (its area
+    /// in 2D), e.g. to compute the intersection over union of two intervals with
+    /// overlapping_volume.
+    fn volume(&self) -> Self::Scalar {
+        (0..self.dimension())
+            .map(|k| self.max_at(k) - self.min_at(k))
+            .product()
+    }
+
+    /// Returns the measure of the boundary of self: its perimeter in 2D, its surface area in 3D
+    /// etc. (and 2 in 1D, for the two endpoints).
+    /// Computed as twice the sum, over every dimension, of the product of the other extents.
+    fn surface_measure(&self) -> Self::Scalar {
+        let d = self.dimension();
+        let face = |k: usize| -> Self::Scalar {
+            (0..d)
+                .filter(|&j| j != k)
+                .map(|j| self.max_at(j) - self.min_at(j))
+                .product()
+        };
+        let faces = (1..d).fold(face(0), |acc, k| acc + face(k));
+        faces + faces
+    }
+
+    /// Returns the squared euclidean distance between the centers of self and o (see avg_at),
+    /// e.g. to rank the matches of a query by how centered they are on it.
+    /// The differences are computed as max - min, so this is safe for unsigned scalars.
+    fn centroid_distance_sq<I: Interval<K, Scalar = Self::Scalar>>(&self, o: &I) -> Self::Scalar {
+        (0..self.dimension())
+            .map(|k| {
+                let (a, b) = (self.avg_at(k), o.avg_at(k));
+                max(a, b) - min(a, b)
+            })
+            .fold(Self::Scalar::zero(), |acc, d| acc + d * d)
+    }
+
+    /// Returns the bounds of self translated by offset, e.g. to build a BoxInterval query.
+    /// Only meaningful for compile-time known dimensions.
+    fn translated(&self, offset: [Self::Scalar; K]) -> [(Self::Scalar, Self::Scalar); K] {
+        std::array::from_fn(|k| (self.min_at(k) + offset[k], self.max_at(k) + offset[k]))
+    }
+
+    /// Returns the bounds of self scaled by factor (w.r.t the origin), e.g. to build a
+    /// BoxInterval query. The bounds are swapped for negative factors, so that min <= max.
+    /// Only meaningful for compile-time known dimensions.
+    fn scaled(&self, factor: Self::Scalar) -> [(Self::Scalar, Self::Scalar); K] {
+        std::array::from_fn(|k| {
+            let (a, b) = (self.min_at(k) * factor, self.max_at(k) * factor);
+            (min(a, b), max(a, b))
+        })
+    }
+
+    /// Returns the bounds of self on the given axes, in that order, i.e. its projection on them,
+    /// e.g. to index or query it in a tree of lower dimension (see ProjectedInterval).
+    fn project<const M: usize>(&self, axes: [usize; M]) -> [(Self::Scalar, Self::Scalar); M] {
+        axes.map(|k| (self.min_at(k), self.max_at(k)))
+    }
+
     /// Manual implementation of a comparison function. This allows !Ord types (e.g. floats) to be
     /// used with this library without having to resort to NonNanFloat or equivalents.
     fn cmp_at(&self, k: usize, s: Self::Scalar) -> std::cmp::Ordering {
-        debug_assert!(k < K);
+        debug_assert!(k < self.dimension());
         if self.min_at(k) > s {
             std::cmp::Ordering::Greater
         } else if self.max_at(k) < s {
@@ -136,24 +338,470 @@ pub trait Interval<const K: usize> {
     }
 }
 
+fn natural_cmp<S: Scalar>(a: S, b: S) -> Ordering {
+    a.partial_cmp(&b).unwrap()
+}
+
+/// Moves the intervals for which f returns true from intervals to out.
+fn extract<I, F: FnMut(&I) -> bool>(intervals: &mut LeafIntervals<I>, f: &mut F, out: &mut Vec<I>) {
+    let (extracted, kept): (Vec<I>, Vec<I>) =
+        std::mem::take(intervals).into_iter().partition(|i| f(i));
+    *intervals = leaf(kept);
+    out.extend(extracted);
+}
+
+fn sort_at<const K: usize, I: Interval<K>, C: Fn(I::Scalar, I::Scalar) -> Ordering>(
+    intervals: &mut [I],
+    k: usize,
+    cmp: &C,
+) {
+    intervals.sort_by(|a, b| cmp(a.avg_at(k), b.avg_at(k)));
+}
+
+/// Bounds of i on the kth dimension, ordered w.r.t cmp.
+fn bounds_at_by<const K: usize, I: Interval<K>, C: Fn(I::Scalar, I::Scalar) -> Ordering>(
+    i: &I,
+    k: usize,
+    cmp: &C,
+) -> (I::Scalar, I::Scalar) {
+    let (a, b) = (i.min_at(k), i.max_at(k));
+    if cmp(a, b) == Ordering::Greater {
+        (b, a)
+    } else {
+        (a, b)
+    }
+}
+
+/// Equivalent of Interval::cmp_at, w.r.t cmp.
+fn cmp_at_by<const K: usize, I: Interval<K>, C: Fn(I::Scalar, I::Scalar) -> Ordering>(
+    i: &I,
+    k: usize,
+    s: I::Scalar,
+    cmp: &C,
+) -> Ordering {
+    let (lo, hi) = bounds_at_by(i, k, cmp);
+    if cmp(lo, s) == Ordering::Greater {
+        Ordering::Greater
+    } else if cmp(hi, s) == Ordering::Less {
+        Ordering::Less
+    } else {
+        Ordering::Equal
+    }
+}
+
+/// Same as Interval::overlaps, excluding the intervals only sharing a boundary (i.e. their
+/// intersection has to have a positive volume).
+fn strictly_overlaps<const K: usize, I: Interval<K>, II: Interval<K, Scalar = I::Scalar>>(
+    i: &I,
+    o: &II,
+) -> bool {
+    (0..i.dimension()).all(|k| i.min_at(k) < o.max_at(k) && o.min_at(k) < i.max_at(k))
+}
+
+/// Returns whether i lies entirely inside o (bounds included) on all dimensions.
+fn is_contained<const K: usize, I: Interval<K>, II: Interval<K, Scalar = I::Scalar>>(
+    i: &I,
+    o: &II,
+) -> bool {
+    (0..i.dimension()).all(|k| o.min_at(k) <= i.min_at(k) && i.max_at(k) <= o.max_at(k))
+}
+
+/// Bounds of the given intervals on every dimension, or None if some of them wrap around (i.e.
+/// min_at > max_at, see Periodic), in which case their union cannot be represented.
+fn bounding_box<const K: usize, I: Interval<K>>(
+    intervals: &[I],
+    dimension: usize,
+) -> Option<Vec<(I::Scalar, I::Scalar)>> {
+    let mut bbox: Vec<(I::Scalar, I::Scalar)> = Vec::with_capacity(dimension);
+    for i in intervals {
+        for k in 0..dimension {
+            let (lo, hi) = (i.min_at(k), i.max_at(k));
+            if lo > hi {
+                return None;
+            }
+            match bbox.get_mut(k) {
+                Some(b) => *b = (min(b.0, lo), max(b.1, hi)),
+                None => bbox.push((lo, hi)),
+            }
+        }
+    }
+    Some(bbox)
+}
+
+/// Equivalent of Interval::overlaps, w.r.t cmp.
+fn overlaps_by<
+    const K: usize,
+    I: Interval<K>,
+    II: Interval<K, Scalar = I::Scalar>,
+    C: Fn(I::Scalar, I::Scalar) -> Ordering,
+>(
+    i: &I,
+    o: &II,
+    cmp: &C,
+) -> bool {
+    (0..i.dimension()).all(|k| {
+        let (lo, hi) = bounds_at_by(i, k, cmp);
+        let (o_lo, o_hi) = bounds_at_by(o, k, cmp);
+        cmp(lo, o_hi) != Ordering::Greater && cmp(o_lo, hi) != Ordering::Greater
+    })
+}
+
+/// Where the intervals only touching the median of a node (i.e. with min_at or max_at equal to
+/// it) go during construction.
+/// Sending them to the children instead of the center keeps the center leaves small when many
+/// endpoints coincide, but only intervals actually straddling the median can stay in the center.
+/// The bias is ignored for nodes where it would send all the intervals to the same child.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MedianBias {
+    /// Keep them in the center (default).
+    #[default]
+    Center,
+    /// Send the intervals ending at the median to the left child.
+    Left,
+    /// Send the intervals starting at the median to the right child.
+    Right,
+}
+
+/// Order of the results of IntervalTreeNode::range_search_sorted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortKey {
+    /// Increasing `avg_at(k)`.
+    ByDimension(usize),
+    /// Decreasing overlapping volume with the query.
+    ByOverlapVolume,
+}
+
+/// Parameters of the construction, shared by the whole recursion.
+struct BuildOptions<C> {
+    cmp: C,
+    bias: MedianBias,
+    /// Maximum number of edges from the root to any node.
+    max_depth: Option<usize>,
+    /// Whether to split at the median of the bounds of the intervals, when most of them straddle
+    /// the median of their averages.
+    tie_aware: bool,
+    /// The order in which the dimensions are split on, empty for the natural one (0, 1, etc.).
+    order: Vec<usize>,
+    /// Whether cmp is a custom comparator, in which case the bounds of the intervals are compared
+    /// with it, instead of relying on their own cmp_at (which may be overriden, see Periodic).
+    custom_cmp: bool,
+    /// Whether to assert that all the intervals have the same dimension, at every node.
+    check_dimensions: bool,
+}
+
+impl<C> BuildOptions<C> {
+    fn new(cmp: C) -> BuildOptions<C> {
+        BuildOptions {
+            cmp,
+            bias: MedianBias::Center,
+            max_depth: None,
+            tie_aware: false,
+            order: Vec::new(),
+            custom_cmp: false,
+            check_dimensions: true,
+        }
+    }
+
+    /// The dimension the root of the tree splits on.
+    fn first_dimension(&self) -> usize {
+        self.order.first().copied().unwrap_or(0)
+    }
+
+    /// The dimension the center subtree of a node splitting on k splits on, if any.
+    fn next_dimension(&self, k: usize, dimension: usize) -> Option<usize> {
+        if self.order.is_empty() {
+            return (k + 1 < dimension).then_some(k + 1);
+        }
+        let position = self.order.iter().position(|&o| o == k)?;
+        self.order.get(position + 1).copied()
+    }
+
+    /// The dimensions split on after k (excluded), in order.
+    fn dimensions_after(&self, k: usize, dimension: usize) -> impl Iterator<Item = usize> + '_ {
+        std::iter::successors(self.next_dimension(k, dimension), move |&k| {
+            self.next_dimension(k, dimension)
+        })
+    }
+
+    fn cmp_at<const K: usize, I: Interval<K>>(&self, i: &I, k: usize, s: I::Scalar) -> Ordering
+    where
+        C: Fn(I::Scalar, I::Scalar) -> Ordering,
+    {
+        if self.custom_cmp {
+            cmp_at_by(i, k, s, &self.cmp)
+        } else {
+            i.cmp_at(k, s)
+        }
+    }
+
+    fn bounds_at<const K: usize, I: Interval<K>>(&self, i: &I, k: usize) -> (I::Scalar, I::Scalar)
+    where
+        C: Fn(I::Scalar, I::Scalar) -> Ordering,
+    {
+        if self.custom_cmp {
+            bounds_at_by(i, k, &self.cmp)
+        } else {
+            (i.min_at(k), i.max_at(k))
+        }
+    }
+}
+
 /// Should not be matched on.
 /// Internal representation of the tree, based on whether there are further dimensions to process.
+#[derive(Clone)]
 pub enum NodeContent<I: Interval<K>, const K: usize> {
     Subtree(Box<IntervalTreeNode<I, K>>),
-    Leaf(Vec<I>),
+    Leaf(LeafIntervals<I>),
+}
+
+/// Storage of the intervals of a leaf. With the smallvec feature, leaves holding up to 2
+/// intervals (i.e. most of them) do not allocate.
+#[cfg(not(feature = "smallvec"))]
+pub type LeafIntervals<I> = Vec<I>;
+#[cfg(feature = "smallvec")]
+pub type LeafIntervals<I> = smallvec::SmallVec<[I; 2]>;
+
+#[cfg(not(feature = "smallvec"))]
+fn leaf<I>(intervals: Vec<I>) -> LeafIntervals<I> {
+    intervals
+}
+
+#[cfg(feature = "smallvec")]
+fn leaf<I>(intervals: Vec<I>) -> LeafIntervals<I> {
+    intervals.into()
+}
+
+/// Number of bytes allocated on the heap by a leaf.
+#[cfg(not(feature = "smallvec"))]
+fn leaf_heap_bytes<I>(intervals: &LeafIntervals<I>) -> usize {
+    intervals.capacity() * std::mem::size_of::<I>()
+}
+
+#[cfg(feature = "smallvec")]
+fn leaf_heap_bytes<I>(intervals: &LeafIntervals<I>) -> usize {
+    if intervals.spilled() {
+        intervals.capacity() * std::mem::size_of::<I>()
+    } else {
+        0
+    }
 }
 
 /// Implementation detail.
 /// Contains sub-trees, which either contain the raw intervals, or the nodes of trees at dimension
-/// k+1.
+/// k+1 (or the next one in the order given to from_intervals_order).
 /// Also contain the left and right nodes, which contain intervals with lower or greater values
 /// respectively for dimension k.
+/// The tree only owns its intervals (through Box's and Vec's), so it is Send (resp. Sync)
+/// whenever I and I::Scalar are, e.g. to be shared between threads for querying.
+#[derive(Clone)]
 pub struct IntervalTreeNode<I: Interval<K>, const K: usize> {
     pub(crate) center: NodeContent<I, K>,
     pub(crate) center_val: I::Scalar,
     pub(crate) k: usize,
     pub(crate) lt_nodes: Option<Box<IntervalTreeNode<I, K>>>,
     pub(crate) gt_nodes: Option<Box<IntervalTreeNode<I, K>>>,
+    /// Bounds of all the intervals of this node and its descendants, on every dimension, used by
+    /// range_search to skip the nodes the query does not overlap on any dimension.
+    /// None when unknown (e.g. after map, or with wrapping intervals, see Periodic).
+    pub(crate) bbox: Option<Vec<(I::Scalar, I::Scalar)>>,
+}
+
+/// Plain-data description of a node of the tree, as returned by IntervalTreeNode::to_structure.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TreeNodeInfo<S> {
+    /// The dimension this node splits on.
+    pub k: usize,
+    /// The value this node splits at.
+    pub center_val: S,
+    /// The number of intervals contained in the center of this node (leaf or subtree).
+    pub center_len: usize,
+    /// The center subtree, when the center is not a leaf.
+    pub center: Option<Box<TreeNodeInfo<S>>>,
+    pub lt_nodes: Option<Box<TreeNodeInfo<S>>>,
+    pub gt_nodes: Option<Box<TreeNodeInfo<S>>>,
+}
+
+/// Step from a node to one of its children, identifying nodes by their path from the root.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PathStep {
+    Lt,
+    Center,
+    Gt,
+}
+
+/// Position of an interval in a tree, as returned by IntervalTreeNode::range_search_handles: the
+/// path from the root to the node whose leaf holds it, and its index in that leaf.
+/// A handle stays valid as long as the tree is not mutated, apart from the removal of other
+/// handles through remove_handle.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct IntervalHandle {
+    path: Vec<PathStep>,
+    index: usize,
+}
+
+impl IntervalHandle {
+    /// The path from the root to the node holding the interval.
+    pub fn path(&self) -> &[PathStep] {
+        &self.path
+    }
+
+    /// The index of the interval in the leaf of its node.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+}
+
+/// Load of a node over a batch of queries, see HeatmapReport.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct NodeHeat {
+    /// Number of queries which descended into this node.
+    pub visits: usize,
+    /// Number of matches found in the leaf of this node, over all the queries.
+    pub matches: usize,
+}
+
+/// Load of the nodes of a tree over a batch of queries, as returned by
+/// IntervalTreeNode::query_heatmap.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HeatmapReport {
+    /// The load of every node visited by at least one query, by path from the root (the root
+    /// itself being the empty path).
+    pub nodes: BTreeMap<Vec<PathStep>, NodeHeat>,
+}
+
+/// Violation of the structure of a tree, as reported by IntervalTreeNode::validate_invariants.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InvariantError {
+    /// An interval of the lt (resp. gt) child of a node splitting on k ends after (resp. starts
+    /// before) its center_val. side is Less for the lt child, Greater for the gt one.
+    MisplacedInterval { k: usize, side: Ordering },
+    /// An interval does not have the same dimension as the first interval of the tree.
+    DimensionMismatch { expected: usize, found: usize },
+    /// A node splits on k while it should split on expected (i.e. the dimension of its parent,
+    /// or the next one for center subtrees), or on a dimension the intervals do not have.
+    InvalidSplitDimension { expected: usize, found: usize },
+}
+
+impl std::fmt::Display for InvariantError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InvariantError::MisplacedInterval { k, side } => write!(
+                f,
+                "an interval of the {} child of a node splitting on dimension {k} crosses its center value",
+                if *side == Ordering::Less { "lt" } else { "gt" }
+            ),
+            InvariantError::DimensionMismatch { expected, found } => write!(
+                f,
+                "an interval has dimension {found} instead of {expected}"
+            ),
+            InvariantError::InvalidSplitDimension { expected, found } => write!(
+                f,
+                "a node splits on dimension {found} instead of {expected}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for InvariantError {}
+
+/// Reason why a tree cannot be built from some intervals, as returned by
+/// IntervalTreeNode::try_from_intervals.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BuildError {
+    /// No interval was given.
+    Empty,
+    /// An interval does not have the same dimension as the first one.
+    DimensionMismatch { expected: usize, found: usize },
+    /// A bound of an interval on the kth dimension cannot be compared (e.g. NaN).
+    InvalidScalar { k: usize },
+}
+
+impl std::fmt::Display for BuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BuildError::Empty => write!(f, "cannot build a tree from no interval"),
+            BuildError::DimensionMismatch { expected, found } => {
+                write!(f, "an interval has dimension {found} instead of {expected}")
+            }
+            BuildError::InvalidScalar { k } => write!(
+                f,
+                "an interval has a bound on dimension {k} which cannot be compared"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+/// Summary of the shape of a tree, as returned by IntervalTreeNode::stats.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TreeStats {
+    /// Number of nodes, including the ones of the center subtrees.
+    pub nodes: usize,
+    /// Number of leaves, i.e. of Vec's actually holding intervals.
+    pub leaves: usize,
+    /// Number of intervals stored in the tree.
+    pub intervals: usize,
+    /// Number of nodes on the longest path from the root to a leaf.
+    pub height: usize,
+    /// Number of intervals the leaves can hold without reallocating.
+    pub leaf_capacity: usize,
+}
+
+/// Statistics collected while building a tree, as returned by
+/// IntervalTreeNode::from_intervals_reported.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BuildReport {
+    /// Number of nodes created, including the ones of the center subtrees.
+    pub nodes: usize,
+    /// Deepest recursion reached, i.e. number of nodes on the longest path from the root.
+    pub max_depth: usize,
+    /// Number of intervals in the largest leaf.
+    pub largest_leaf: usize,
+    /// Number of intervals stuck in a leaf because they strictly straddle the median of its node,
+    /// while other intervals of the node could be sent to its children.
+    pub straddling: usize,
+}
+
+/// Description of a node, passed to the observer of IntervalTreeNode::from_intervals_observed
+/// once the node (and thus all of its descendants) is built.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NodeBuildEvent<S> {
+    /// Number of nodes from the root to this one (included).
+    pub depth: usize,
+    /// The dimension this node splits on.
+    pub k: usize,
+    /// Number of intervals in this node and its descendants.
+    pub intervals: usize,
+    /// Number of intervals in the center of this node (leaf or subtree).
+    pub center_len: usize,
+    /// Whether the center of this node is a leaf.
+    pub leaf: bool,
+    /// The value this node splits at.
+    pub center_val: S,
+}
+
+/// Result of IntervalTreeNode::explain_query.
+#[derive(Clone, Debug, PartialEq)]
+pub struct QueryExplanation<'a, I> {
+    /// Same as range_search.
+    pub matches: Vec<&'a I>,
+    /// The intervals tested by the query which did not match, i.e. the ones of the leaves it went
+    /// through, with the first dimension on which they do not overlap with it. The intervals of
+    /// the subtrees pruned on their bounding box or split value are not listed.
+    pub near_misses: Vec<(&'a I, usize)>,
+}
+
+/// Match of IntervalTreeNode::range_search_split, split at the bounds of the query.
+/// Only the bounds of the portions are given, as intervals cannot be built back from them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SplitResult<'a, I: Interval<K>, const K: usize> {
+    pub interval: &'a I,
+    /// The portion of the interval inside the query.
+    pub inside: [(I::Scalar, I::Scalar); K],
+    /// The portions of the interval outside of the query, which do not overlap with each other
+    /// (apart from their shared bounds). Empty if the interval lies inside the query.
+    pub outside: Vec<[(I::Scalar, I::Scalar); K]>,
 }
 
 impl<const K: usize, I: Interval<K>> IntervalTreeNode<I, K> {
@@ -163,110 +811,2105 @@ impl<const K: usize, I: Interval<K>> IntervalTreeNode<I, K> {
     /// the search.
     /// TODO: make a "safe" overload to avoid confusing differents elements
     pub fn range_search<II: Interval<K, Scalar = I::Scalar>>(&self, x: &II) -> Vec<&I> {
-        let same_level = match x.cmp_at(self.k, self.center_val) {
-            Ordering::Less => self
-                .lt_nodes
-                .as_ref()
-                .map_or(Vec::new(), |n| n.range_search(x)),
-            Ordering::Greater => self
-                .gt_nodes
-                .as_ref()
-                .map_or(Vec::new(), |n| n.range_search(x)),
-            Ordering::Equal => self
-                .lt_nodes
-                .as_ref()
-                .map_or(Vec::new(), |n| n.range_search(x))
-                .into_iter()
-                .chain(
-                    self.gt_nodes
-                        .as_ref()
-                        .map_or(Vec::new(), |n| n.range_search(x))
-                        .into_iter(),
-                )
-                .collect(),
-        };
+        let mut out = Vec::new();
+        self.range_search_rec(x, &mut out);
+        out
+    }
 
-        match &self.center {
-            NodeContent::Subtree(n) => n.range_search(x),
-            NodeContent::Leaf(intervals) => intervals.iter().filter(|i| i.overlaps(x)).collect(),
-        }
-        .into_iter()
-        .chain(same_level.into_iter())
-        .collect()
+    /// Same as range_search, writing the matches into out (which is cleared first), so that its
+    /// allocation can be reused across queries.
+    pub fn range_search_buf<'a, II: Interval<K, Scalar = I::Scalar>>(
+        &'a self,
+        x: &II,
+        out: &mut Vec<&'a I>,
+    ) {
+        out.clear();
+        self.range_search_rec(x, out);
     }
 
-    /// Creates an IntervalTreeNode given a collection of intervals.
-    /// TODO: make generics, does not have to be a Vec
-    pub fn from_intervals(intervals: Vec<I>) -> IntervalTreeNode<I, K> {
-        IntervalTreeNode::from_intervals_rec(intervals, 0)
+    /// All the matches are appended to the same buffer, to avoid any intermediate allocation.
+    fn range_search_rec<'a, II: Interval<K, Scalar = I::Scalar>>(
+        &'a self,
+        x: &II,
+        out: &mut Vec<&'a I>,
+    ) {
+        self.visit_leaves(x, |intervals| {
+            out.extend(intervals.iter().filter(|i| i.overlaps(x)));
+        });
+    }
+
+    /// Same as range_search, also returning the overlapping volume of each match with x.
+    pub fn range_search_with_volume<II: Interval<K, Scalar = I::Scalar>>(
+        &self,
+        x: &II,
+    ) -> Vec<(&I, I::Scalar)> {
+        let mut out = Vec::new();
+        self.visit_leaves(x, |intervals| {
+            out.extend(
+                intervals
+                    .iter()
+                    .filter_map(|i| i.try_overlapping_volume(x).map(|v| (i, v))),
+            );
+        });
+        out
+    }
+
+    /// Same as range_search, taking the query by value, e.g. for freshly constructed queries.
+    ///
+    /// ```
+    /// use kd_interval_tree::{Interval, IntervalTreeNode};
+    ///
+    /// struct Rectangle([(f64, f64); 2]);
+    ///
+    /// impl Interval<2> for Rectangle {
+    ///     type Scalar = f64;
+    ///     fn min_at(&self, k: usize) -> f64 {
+    ///         self.0[k].0
+    ///     }
+    ///     fn max_at(&self, k: usize) -> f64 {
+    ///         self.0[k].1
+    ///     }
+    /// }
+    ///
+    /// let tree = IntervalTreeNode::from_intervals(vec![
+    ///     Rectangle([(0.0, 1.0), (0.0, 1.0)]),
+    ///     Rectangle([(2.0, 3.0), (2.0, 3.0)]),
+    /// ]);
+    /// let found = tree.range_search_owned(Rectangle([(0.5, 2.5), (0.5, 1.5)]));
+    /// assert_eq!(found.len(), 1);
+    /// assert_eq!(found[0].0, [(0.0, 1.0), (0.0, 1.0)]);
+    /// ```
+    pub fn range_search_owned<II: Interval<K, Scalar = I::Scalar>>(&self, x: II) -> Vec<&I> {
+        self.range_search(&x)
     }
 
-    fn from_intervals_rec(mut intervals: Vec<I>, k: usize) -> IntervalTreeNode<I, K> {
+    /// Same as range_search, querying the axis-aligned box given by its bounds on each
+    /// dimension (inclusive).
+    pub fn range_search_bounds(&self, mins: [I::Scalar; K], maxs: [I::Scalar; K]) -> Vec<&I> {
+        self.range_search(&BoxInterval(std::array::from_fn(|k| (mins[k], maxs[k]))))
+    }
+
+    /// Same as range_search_bounds, for any dimension (including dynamically-sized trees).
+    pub fn range_search_bounds_dyn(&self, mins: &[I::Scalar], maxs: &[I::Scalar]) -> Vec<&I> {
         assert!(
-            !intervals.is_empty(),
-            "Input intervals should not be empty!"
+            mins.len() == maxs.len(),
+            "The query needs as many min as max bounds!"
         );
-        intervals.sort_by(|a, b| a.avg_at(k).partial_cmp(&b.avg_at(k)).unwrap());
-        let median = intervals[intervals.len() / 2].avg_at(k);
-
-        let mut lt_nodes = Vec::new();
-        let mut center = Vec::new();
-        let mut gt_nodes = Vec::new();
-
-        let mut dimension = None;
-        for i in intervals {
-            dimension = match dimension {
-                Some(d) => {
-                    assert!(
-                        i.dimension() == d,
-                        "Intervals need to have the same dimension when transformed into a tree!"
-                    );
-                    dimension
-                }
-                None => Some(i.dimension()),
-            };
+        self.range_search(&SliceBounds { mins, maxs })
+    }
 
-            if i.max_at(k) < median {
-                lt_nodes.push(i);
-            } else if i.min_at(k) > median {
-                gt_nodes.push(i);
-            } else {
-                center.push(i);
+    /// Same as range_search, returning copies of the matches (as Iterator::copied), which avoids
+    /// the indirection for small intervals.
+    pub fn range_search_copied<II: Interval<K, Scalar = I::Scalar>>(&self, x: &II) -> Vec<I>
+    where
+        I: Copy,
+    {
+        self.intersecting(x).copied().collect()
+    }
+
+    /// Same as range_search, with the results wrapped in Cow::Borrowed: they are only cloned when
+    /// (and if) the caller mutates them, e.g. through Cow::to_mut.
+    pub fn range_search_cow<II: Interval<K, Scalar = I::Scalar>>(&self, x: &II) -> Vec<Cow<'_, I>>
+    where
+        I: Clone,
+    {
+        self.range_search(x)
+            .into_iter()
+            .map(Cow::Borrowed)
+            .collect()
+    }
+
+    /// Same as range_search, with the results sorted w.r.t by. The sort is stable, so equal keys
+    /// are left in traversal order.
+    pub fn range_search_sorted<II: Interval<K, Scalar = I::Scalar>>(
+        &self,
+        x: &II,
+        by: SortKey,
+    ) -> Vec<&I> {
+        let mut intervals = self.range_search(x);
+        match by {
+            SortKey::ByDimension(k) => {
+                intervals.sort_by(|a, b| natural_cmp(a.avg_at(k), b.avg_at(k)))
             }
+            SortKey::ByOverlapVolume => intervals
+                .sort_by(|a, b| natural_cmp(b.overlapping_volume(x), a.overlapping_volume(x))),
         }
+        intervals
+    }
 
-        let lt_nodes = if lt_nodes.is_empty() {
-            None
-        } else {
-            Some(Box::new(IntervalTreeNode::from_intervals_rec(lt_nodes, k)))
-        };
+    /// Returns all the Interval's in the tree overlapping with at least one of the queries, each
+    /// of them only once.
+    /// The tree is walked a single time, each node only being visited with the queries reaching
+    /// it.
+    pub fn range_search_multi<II: Interval<K, Scalar = I::Scalar>>(
+        &self,
+        queries: &[II],
+    ) -> Vec<&I> {
+        let mut out = Vec::new();
+        if !queries.is_empty() {
+            self.range_search_multi_rec(&queries.iter().collect::<Vec<_>>(), &mut out);
+        }
+        out
+    }
 
-        let gt_nodes = if gt_nodes.is_empty() {
-            None
-        } else {
-            Some(Box::new(IntervalTreeNode::from_intervals_rec(gt_nodes, k)))
+    /// Expects queries to be non-empty.
+    fn range_search_multi_rec<'a, II: Interval<K, Scalar = I::Scalar>>(
+        &'a self,
+        queries: &[&II],
+        out: &mut Vec<&'a I>,
+    ) {
+        let queries = queries
+            .iter()
+            .filter(|x| self.reaches_bbox(**x))
+            .copied()
+            .collect::<Vec<_>>();
+        if queries.is_empty() {
+            return;
+        }
+        match &self.center {
+            NodeContent::Subtree(n) => n.range_search_multi_rec(&queries, out),
+            NodeContent::Leaf(intervals) => out.extend(
+                intervals
+                    .iter()
+                    .filter(|i| queries.iter().any(|x| i.overlaps(*x))),
+            ),
+        }
+
+        for (step, child) in [
+            (PathStep::Lt, &self.lt_nodes),
+            (PathStep::Gt, &self.gt_nodes),
+        ] {
+            if let Some(n) = child {
+                let reaching = queries
+                    .iter()
+                    .filter(|x| self.reaches(**x, step))
+                    .copied()
+                    .collect::<Vec<_>>();
+                if !reaching.is_empty() {
+                    n.range_search_multi_rec(&reaching, out);
+                }
+            }
+        }
+    }
+
+    /// Same as range_search, for trees built with from_intervals_by: cmp has to be the one used
+    /// during construction, otherwise the results are meaningless.
+    pub fn range_search_by<II, C>(&self, x: &II, cmp: C) -> Vec<&I>
+    where
+        II: Interval<K, Scalar = I::Scalar>,
+        C: Fn(I::Scalar, I::Scalar) -> Ordering,
+    {
+        let mut out = Vec::new();
+        let by = ByCmp { inner: x, cmp };
+        self.visit_leaves(&by, |intervals| {
+            out.extend(intervals.iter().filter(|i| overlaps_by(*i, x, &by.cmp)));
+        });
+        out
+    }
+
+    /// Same as range_search, only keeping the intervals for which pred also returns true (e.g. to
+    /// filter on attributes stored alongside the bounds), in a single traversal.
+    pub fn range_search_filter<II, F>(&self, x: &II, pred: F) -> Vec<&I>
+    where
+        II: Interval<K, Scalar = I::Scalar>,
+        F: Fn(&I) -> bool,
+    {
+        let mut out = Vec::new();
+        self.visit_leaves(x, |intervals| {
+            out.extend(intervals.iter().filter(|i| i.overlaps(x) && pred(i)));
+        });
+        out
+    }
+
+    /// Same as range_search, pairing each match with the depth of the node whose leaf holds it,
+    /// i.e. the number of nodes descended (through the lt, gt or center subtrees) from the root,
+    /// whose own leaf is at depth 0. Useful to see where the cost of a query goes.
+    pub fn range_search_by_depth<II: Interval<K, Scalar = I::Scalar>>(
+        &self,
+        x: &II,
+    ) -> Vec<(usize, &I)> {
+        let mut visitor = DepthVisitor {
+            x,
+            depth: 0,
+            out: Vec::new(),
         };
+        let _ = self.visit(x, &mut visitor);
+        visitor.out
+    }
 
-        let center = if k + 1 < dimension.unwrap() {
-            NodeContent::Subtree(Box::new(IntervalTreeNode::from_intervals_rec(
-                center,
-                k + 1,
-            )))
-        } else {
-            NodeContent::Leaf(center)
+    /// Returns the intervals whose intersection over union with x is at least threshold, i.e.
+    /// overlapping_volume / (volume + x.volume() - overlapping_volume), e.g. to match bounding
+    /// boxes against detections.
+    /// The tree is pruned by overlap first, the ratio being only computed at the leaves. It is
+    /// compared as overlap >= threshold * union to avoid divisions: intervals whose union with x
+    /// has a zero volume (e.g. a point queried by itself) always match.
+    pub fn range_search_iou<II: Interval<K, Scalar = I::Scalar>>(
+        &self,
+        x: &II,
+        threshold: I::Scalar,
+    ) -> Vec<&I> {
+        let query_volume = x.volume();
+        self.range_search_filter(x, |i| {
+            let overlap = i.overlapping_volume(x);
+            overlap >= threshold * (i.volume() + query_volume - overlap)
+        })
+    }
+
+    /// Same as range_search, also listing the intervals the query tested without matching, with
+    /// the first dimension separating them from x, e.g. to understand why an expected match is
+    /// missing. Only meant for debugging: the near misses are collected at every visited leaf.
+    pub fn explain_query<II: Interval<K, Scalar = I::Scalar>>(
+        &self,
+        x: &II,
+    ) -> QueryExplanation<'_, I> {
+        let mut explanation = QueryExplanation {
+            matches: Vec::new(),
+            near_misses: Vec::new(),
         };
+        self.visit_leaves(x, |intervals| {
+            for i in intervals {
+                match (0..i.dimension()).find(|&k| !i.overlaps_at(k, x)) {
+                    None => explanation.matches.push(i),
+                    Some(k) => explanation.near_misses.push((i, k)),
+                }
+            }
+        });
+        explanation
+    }
 
-        IntervalTreeNode {
-            center_val: median,
-            k,
-            center,
-            lt_nodes,
-            gt_nodes,
+    /// Runs every query, recording for each node how many of them descended into it, and how many
+    /// matches its leaf contributed, e.g. to find the over-subscribed regions of the tree.
+    pub fn query_heatmap<II: Interval<K, Scalar = I::Scalar>>(
+        &self,
+        queries: &[II],
+    ) -> HeatmapReport {
+        let mut report = HeatmapReport::default();
+        for x in queries {
+            let _ = self.visit(
+                x,
+                &mut HeatmapVisitor {
+                    x,
+                    path: Vec::new(),
+                    report: &mut report,
+                },
+            );
         }
+        report
     }
 
-    /// Returns an interator over all intervals in the tree.
-    pub fn iter(&self) -> IntervalTreeIterator<I, K> {
-        IntervalTreeIterator::new(self)
+    /// Same as range_search, splitting each match at the bounds of x, into the portion inside x
+    /// and the ones outside of it (see SplitResult), e.g. to compute an exact coverage.
+    /// The outside portions are cut one dimension after the other: the ones on the kth dimension
+    /// span the inside portion on the previous dimensions, and the whole interval on the next ones.
+    /// Only meaningful for compile-time known dimensions.
+    pub fn range_search_split<II: Interval<K, Scalar = I::Scalar>>(
+        &self,
+        x: &II,
+    ) -> Vec<SplitResult<'_, I, K>> {
+        self.range_search(x)
+            .into_iter()
+            .map(|i| {
+                let inside = i.clamp_bounds(x).unwrap();
+                let mut outside = Vec::new();
+                let mut remainder: [(I::Scalar, I::Scalar); K] =
+                    std::array::from_fn(|k| (i.min_at(k), i.max_at(k)));
+                for k in 0..K {
+                    let (lo, hi) = remainder[k];
+                    if lo < inside[k].0 {
+                        let mut below = remainder;
+                        below[k] = (lo, inside[k].0);
+                        outside.push(below);
+                    }
+                    if inside[k].1 < hi {
+                        let mut above = remainder;
+                        above[k] = (inside[k].1, hi);
+                        outside.push(above);
+                    }
+                    remainder[k] = inside[k];
+                }
+                SplitResult {
+                    interval: i,
+                    inside,
+                    outside,
+                }
+            })
+            .collect()
+    }
+
+    /// Same as range_search, splitting the matches between the ones entirely inside x (bounds
+    /// included), and the ones only partially overlapping with it, in a single traversal.
+    pub fn range_search_partitioned<II: Interval<K, Scalar = I::Scalar>>(
+        &self,
+        x: &II,
+    ) -> (Vec<&I>, Vec<&I>) {
+        let mut contained = Vec::new();
+        let mut partial = Vec::new();
+        self.visit_leaves(x, |intervals| {
+            for i in intervals.iter().filter(|i| i.overlaps(x)) {
+                if is_contained(i, x) {
+                    contained.push(i);
+                } else {
+                    partial.push(i);
+                }
+            }
+        });
+        (contained, partial)
+    }
+
+    /// Same as range_search, excluding the intervals only touching x (i.e. sharing a boundary
+    /// with it): the overlap has to have a positive volume. Degenerate queries (e.g. points) thus
+    /// never match.
+    pub fn range_search_strict<II: Interval<K, Scalar = I::Scalar>>(&self, x: &II) -> Vec<&I> {
+        let mut out = Vec::new();
+        // The pruning is the same as range_search's, as the children can contain intervals
+        // touching center_val (see from_intervals_biased)
+        self.visit_leaves(x, |intervals| {
+            out.extend(intervals.iter().filter(|i| strictly_overlaps(*i, x)));
+        });
+        out
+    }
+
+    /// Same as range_search, but with x inflated by margin on every dimension, i.e. returns the
+    /// intervals overlapping with [min_at(k) - margin, max_at(k) + margin] for every k.
+    /// Beware of unsigned scalars, for which the subtraction can underflow.
+    pub fn range_search_expanded<II: Interval<K, Scalar = I::Scalar>>(
+        &self,
+        x: &II,
+        margin: I::Scalar,
+    ) -> Vec<&I> {
+        self.range_search(&Expanded { inner: x, margin })
+    }
+
+    /// Same as range_search, with the overlaps tested up to a tolerance of eps (see
+    /// Interval::overlaps_within), the subtrees being pruned as with range_search_expanded.
+    pub fn range_search_eps<II: Interval<K, Scalar = I::Scalar>>(
+        &self,
+        x: &II,
+        eps: I::Scalar,
+    ) -> Vec<&I> {
+        let mut out = Vec::new();
+        let expanded = Expanded {
+            inner: x,
+            margin: eps,
+        };
+        self.visit_leaves(&expanded, |intervals| {
+            out.extend(intervals.iter().filter(|i| i.overlaps_within(x, eps)));
+        });
+        out
+    }
+
+    /// Same as range_search, for a query x whose bounds are of another scalar type: they are
+    /// converted on the fly with convert (e.g. `|v| v as f64` to query a f64 tree with f32
+    /// bounds). convert should preserve the ordering of the bounds.
+    pub fn range_search_convert<S2, II, F>(&self, x: &II, convert: F) -> Vec<&I>
+    where
+        S2: Scalar,
+        II: Interval<K, Scalar = S2>,
+        F: Fn(S2) -> I::Scalar,
+    {
+        self.range_search(&Converted { inner: x, convert })
+    }
+
+    /// Same as range_search, but only checks overlaps on the given dimensions: the other ones are
+    /// considered unbounded.
+    pub fn range_search_axes<II: Interval<K, Scalar = I::Scalar>>(
+        &self,
+        x: &II,
+        axes: &[usize],
+    ) -> Vec<&I> {
+        let mut out = Vec::new();
+        self.visit_leaves(&OnAxes { inner: x, axes }, |intervals| {
+            out.extend(
+                intervals
+                    .iter()
+                    .filter(|i| axes.iter().all(|&k| i.overlaps_at(k, x))),
+            );
+        });
+        out
+    }
+
+    /// Returns all the Interval's in the tree whose projection on the kth dimension overlaps with
+    /// [lo, hi], regardless of their other dimensions.
+    pub fn scan_axis(&self, k: usize, lo: I::Scalar, hi: I::Scalar) -> Vec<&I> {
+        self.range_search_axes(&AxisBounds { axis: k, lo, hi }, &[k])
+    }
+
+    /// Calls f on every interval in the tree overlapping with x, in the same order as
+    /// range_search.
+    /// The traversal stops as soon as f returns an error, which is then returned as is.
+    pub fn range_search_try<'a, II, E, F>(&'a self, x: &II, mut f: F) -> Result<(), E>
+    where
+        II: Interval<K, Scalar = I::Scalar>,
+        F: FnMut(&'a I) -> Result<(), E>,
+    {
+        let visited = self.visit(
+            x,
+            &mut Leaves(|intervals: &'a LeafIntervals<I>| {
+                for i in intervals.iter().filter(|i| i.overlaps(x)) {
+                    if let Err(e) = f(i) {
+                        return ControlFlow::Break(e);
+                    }
+                }
+                ControlFlow::Continue(())
+            }),
+        );
+        match visited {
+            ControlFlow::Continue(()) => Ok(()),
+            ControlFlow::Break(e) => Err(e),
+        }
+    }
+
+    /// Same as range_search, but checks cancel at every node of the traversal, returning None as
+    /// soon as it is set (e.g. by a UI thread whose query became stale).
+    pub fn range_search_cancellable<II: Interval<K, Scalar = I::Scalar>>(
+        &self,
+        x: &II,
+        cancel: &AtomicBool,
+    ) -> Option<Vec<&I>> {
+        let mut visitor = CancellableVisitor {
+            x,
+            cancel,
+            out: Vec::new(),
+        };
+        match self.visit(x, &mut visitor) {
+            ControlFlow::Continue(()) => Some(visitor.out),
+            ControlFlow::Break(()) => None,
+        }
+    }
+
+    /// Returns whether any interval of the tree overlaps with x, stopping at the first match.
+    pub fn any_overlap<II: Interval<K, Scalar = I::Scalar>>(&self, x: &II) -> bool {
+        self.range_search_try(x, |_| Err(())).is_err()
+    }
+
+    /// Returns the interval of the tree with the greatest overlapping volume with x, along with
+    /// that volume, or None if none of them overlaps with x. Ties may return any of them.
+    pub fn best_overlap<II: Interval<K, Scalar = I::Scalar>>(
+        &self,
+        x: &II,
+    ) -> Option<(&I, I::Scalar)> {
+        let mut best: Option<(&I, I::Scalar)> = None;
+        let _ = self.range_search_try(x, |i| {
+            let volume = i.overlapping_volume(x);
+            if best.is_none_or(|(_, v)| volume > v) {
+                best = Some((i, volume));
+            }
+            Ok::<(), Infallible>(())
+        });
+        best
+    }
+
+    /// Returns the number of intervals of the tree overlapping with x, without collecting them.
+    pub fn count_overlapping<II: Interval<K, Scalar = I::Scalar>>(&self, x: &II) -> usize {
+        self.sum_weights(x, |_| 1)
+    }
+
+    /// Returns the sum of weight(i) over all the intervals i of the tree overlapping with x,
+    /// without collecting them.
+    pub fn sum_weights<II, W, F>(&self, x: &II, weight: F) -> W
+    where
+        II: Interval<K, Scalar = I::Scalar>,
+        W: NumAssign + Copy,
+        F: Fn(&I) -> W,
+    {
+        let mut sum = W::zero();
+        let _ = self.range_search_try(x, |i| {
+            sum += weight(i);
+            Ok::<(), Infallible>(())
+        });
+        sum
+    }
+
+    /// Returns whether every query overlaps with at least one interval of the tree, stopping at
+    /// the first query without any match.
+    pub fn all_queries_hit<II: Interval<K, Scalar = I::Scalar>>(&self, queries: &[II]) -> bool {
+        queries.iter().all(|q| self.any_overlap(q))
+    }
+
+    /// Returns the interval of the tree closest to x, i.e. with the smallest euclidean distance
+    /// between their closest points (0 when they overlap).
+    /// Ties may return any of the closest intervals.
+    pub fn nearest_to<II: Interval<K, Scalar = I::Scalar>>(&self, x: &II) -> Option<&I> {
+        let mut best = None;
+        self.nearest_to_rec(x, &mut best);
+        best.map(|(i, _)| i)
+    }
+
+    fn nearest_to_rec<'a, II: Interval<K, Scalar = I::Scalar>>(
+        &'a self,
+        x: &II,
+        best: &mut Option<(&'a I, I::Scalar)>,
+    ) {
+        match &self.center {
+            NodeContent::Subtree(n) => n.nearest_to_rec(x, best),
+            NodeContent::Leaf(intervals) => {
+                for i in intervals {
+                    let d = distance_sq(i, x);
+                    if best.is_none_or(|(_, best_d)| d < best_d) {
+                        *best = Some((i, d));
+                    }
+                }
+            }
+        }
+
+        // All the intervals of lt_nodes (resp. gt_nodes) end before (resp. start after)
+        // center_val, which gives a lower bound of their distance to x
+        let lt_bound = if x.min_at(self.k) > self.center_val {
+            x.min_at(self.k) - self.center_val
+        } else {
+            I::Scalar::zero()
+        };
+        let gt_bound = if x.max_at(self.k) < self.center_val {
+            self.center_val - x.max_at(self.k)
+        } else {
+            I::Scalar::zero()
+        };
+        let mut children = [(&self.lt_nodes, lt_bound), (&self.gt_nodes, gt_bound)];
+        if gt_bound < lt_bound {
+            children.swap(0, 1);
+        }
+        for (child, bound) in children {
+            if let Some(n) = child {
+                if best.is_none_or(|(_, best_d)| bound * bound < best_d) {
+                    n.nearest_to_rec(x, best);
+                }
+            }
+        }
+    }
+
+    /// Same as range_search, returning a lazy iterator over the overlapping intervals instead.
+    pub fn intersecting<'a, II: Interval<K, Scalar = I::Scalar>>(
+        &'a self,
+        x: &'a II,
+    ) -> Intersecting<'a, I, II, K> {
+        Intersecting::new(self, x)
+    }
+
+    /// Returns an iterator over all intervals in the tree, sorted by increasing distance to p (see
+    /// nearest_to). The k nearest intervals are simply given by iter_by_distance(p).take(k).
+    pub fn iter_by_distance<'a, 'p, P: Point<K, Scalar = I::Scalar>>(
+        &'a self,
+        p: &'p P,
+    ) -> DistanceIter<'a, 'p, I, P, K> {
+        DistanceIter::new(self, p)
+    }
+
+    /// Returns, for every interval in the tree, the number of *other* intervals of the tree
+    /// overlapping with it.
+    /// This runs one range_search per stored interval, i.e. costs O(n * query).
+    pub fn overlap_degrees(&self) -> Vec<(&I, usize)> {
+        self.iter()
+            .map(|i| {
+                let degree = self
+                    .range_search(i)
+                    .into_iter()
+                    .filter(|o| !std::ptr::eq(*o, i))
+                    .count();
+                (i, degree)
+            })
+            .collect()
+    }
+
+    /// Creates an IntervalTreeNode given a collection of intervals.
+    /// TODO: make generics, does not have to be a Vec
+    ///
+    /// # Panics
+    ///
+    /// Panics if intervals is empty, or if they do not all have the same dimension (see
+    /// try_from_intervals for a non-panicking construction).
+    pub fn from_intervals(intervals: Vec<I>) -> IntervalTreeNode<I, K> {
+        IntervalTreeNode::build(intervals, &BuildOptions::new(natural_cmp)).0
+    }
+
+    /// Same as from_intervals, shuffling the intervals deterministically (w.r.t seed) first, to
+    /// break pathological input orders.
+    /// This only affects the shape of the tree (as the intervals sharing the same average are left
+    /// in input order by the construction), not the results of the queries.
+    #[cfg(feature = "rand")]
+    pub fn from_intervals_seeded(mut intervals: Vec<I>, seed: u64) -> IntervalTreeNode<I, K> {
+        use rand::seq::SliceRandom;
+        use rand::SeedableRng;
+        intervals.shuffle(&mut rand::rngs::StdRng::seed_from_u64(seed));
+        IntervalTreeNode::from_intervals(intervals)
+    }
+
+    /// Creates an IntervalTreeNode for each of the given groups (none of which can be empty),
+    /// building the trees in parallel on the rayon thread pool (each tree being built
+    /// sequentially), e.g. for many small indexes such as one per tile.
+    #[cfg(feature = "rayon")]
+    pub fn from_interval_groups_par(groups: Vec<Vec<I>>) -> Vec<IntervalTreeNode<I, K>>
+    where
+        I: Send,
+        I::Scalar: Send,
+    {
+        use rayon::prelude::*;
+        groups
+            .into_par_iter()
+            .map(IntervalTreeNode::from_intervals)
+            .collect()
+    }
+
+    /// Same as from_intervals, returning an error instead of panicking when the intervals are
+    /// invalid, e.g. when they come from an untrusted source.
+    pub fn try_from_intervals(intervals: Vec<I>) -> Result<IntervalTreeNode<I, K>, BuildError> {
+        let dimension = intervals.first().ok_or(BuildError::Empty)?.dimension();
+        for i in &intervals {
+            if i.dimension() != dimension {
+                return Err(BuildError::DimensionMismatch {
+                    expected: dimension,
+                    found: i.dimension(),
+                });
+            }
+            for k in 0..dimension {
+                // Only NaN-like values are not comparable with themselves
+                let (lo, hi) = (i.min_at(k), i.max_at(k));
+                if lo.partial_cmp(&lo).is_none() || hi.partial_cmp(&hi).is_none() {
+                    return Err(BuildError::InvalidScalar { k });
+                }
+            }
+        }
+        Ok(IntervalTreeNode::from_intervals(intervals))
+    }
+
+    /// Same as from_intervals, but also returns statistics about the construction, to help
+    /// diagnosing pathological inputs.
+    pub fn from_intervals_reported(intervals: Vec<I>) -> (IntervalTreeNode<I, K>, BuildReport) {
+        IntervalTreeNode::build(intervals, &BuildOptions::new(natural_cmp))
+    }
+
+    /// Creates an IntervalTreeNode given a collection of intervals already sorted by `avg_at(0)`.
+    /// The partitioning is stable, so the children of a node stay sorted and the intervals only
+    /// get sorted again when switching to the next dimension.
+    /// The ordering is checked in debug builds only: unsorted input in release mode yields a
+    /// valid but possibly badly balanced tree.
+    pub fn from_presorted(intervals: Vec<I>) -> IntervalTreeNode<I, K> {
+        debug_assert!(
+            intervals
+                .windows(2)
+                .all(|w| w[0].avg_at(0) <= w[1].avg_at(0)),
+            "Input intervals should be sorted by avg_at(0)!"
+        );
+        let options = BuildOptions::new(natural_cmp);
+        IntervalTreeNode::from_intervals_rec(
+            intervals,
+            0,
+            1,
+            &options,
+            &mut BuildReport::default(),
+            &mut |_| {},
+            &|intervals, k| sort_at(intervals, k, &options.cmp),
+        )
+    }
+
+    /// Creates an IntervalTreeNode given a collection of intervals, along with the permutations
+    /// sorting them on each dimension: orders[k] lists the positions of the intervals in the
+    /// input, by increasing avg_at(k) (e.g. as computed once for a large dataset).
+    /// The intervals are then never sorted during the construction: they are ordered by looking
+    /// up their rank in these permutations instead.
+    /// Whether the permutations actually sort the intervals is only checked in debug builds:
+    /// unsorted ones in release mode yield a valid but possibly badly balanced tree.
+    ///
+    /// # Panics
+    ///
+    /// Panics if intervals is empty, as from_intervals does, if K = 0 (dynamically-sized
+    /// intervals are not supported, as orders needs one permutation per dimension), if the
+    /// intervals are not of dimension K, or if some orders[k] is not a permutation of the
+    /// positions of the intervals.
+    pub fn from_sorted_by_dimension(
+        intervals: Vec<I>,
+        orders: [Vec<usize>; K],
+    ) -> IntervalTreeNode<I, K> {
+        assert!(
+            K > 0,
+            "from_sorted_by_dimension does not support dynamically-sized intervals!"
+        );
+        for i in &intervals {
+            assert!(
+                i.dimension() == K,
+                "Intervals need to be of dimension {K}, as orders has {K} permutations!"
+            );
+        }
+        for (k, order) in orders.iter().enumerate() {
+            let mut seen = vec![false; intervals.len()];
+            assert!(
+                order.len() == intervals.len()
+                    && order
+                        .iter()
+                        .all(|&i| i < seen.len() && !std::mem::replace(&mut seen[i], true)),
+                "orders[{k}] should be a permutation of the intervals!"
+            );
+            debug_assert!(
+                order
+                    .windows(2)
+                    .all(|w| intervals[w[0]].avg_at(k) <= intervals[w[1]].avg_at(k)),
+                "orders[{k}] should sort the intervals by avg_at({k})!"
+            );
+        }
+
+        let mut ranks = vec![vec![0; intervals.len()]; K];
+        for (order, ranks) in orders.iter().zip(&mut ranks) {
+            for (rank, &i) in order.iter().enumerate() {
+                ranks[i] = rank;
+            }
+        }
+        let mut slots = intervals.into_iter().map(Some).collect::<Vec<_>>();
+        let sorted = orders[0]
+            .iter()
+            .map(|&i| Indexed::new(slots[i].take().unwrap(), i))
+            .collect();
+
+        let options = BuildOptions::new(natural_cmp);
+        let mut tree = IntervalTreeNode::from_intervals_rec(
+            sorted,
+            0,
+            1,
+            &options,
+            &mut BuildReport::default(),
+            &mut |_| {},
+            &|intervals: &mut [Indexed<I>], k| {
+                intervals.sort_unstable_by_key(|i| ranks[k][i.index()])
+            },
+        )
+        .map_rec(&mut Indexed::into_inner, true);
+        // The leaves reuse the allocations of the (larger) indexed intervals
+        tree.compact();
+        tree
+    }
+
+    /// Creates an IntervalTreeNode given a collection of intervals, ordering the scalars with cmp
+    /// instead of their natural order (e.g. for custom NaN policies).
+    /// cmp should define a total order, for which min_at and max_at are the extremities of each
+    /// interval, in any order (i.e. a reversed comparator is fine).
+    /// The resulting tree should only be queried with range_search_by, using the same comparator.
+    pub fn from_intervals_by<C: Fn(I::Scalar, I::Scalar) -> Ordering>(
+        intervals: Vec<I>,
+        cmp: C,
+    ) -> IntervalTreeNode<I, K> {
+        let options = BuildOptions {
+            custom_cmp: true,
+            ..BuildOptions::new(cmp)
+        };
+        IntervalTreeNode::build(intervals, &options).0
+    }
+
+    /// Creates an IntervalTreeNode given a collection of intervals, sending the intervals which
+    /// only touch the median of a node to one of its children instead of its center, see
+    /// MedianBias.
+    pub fn from_intervals_biased(intervals: Vec<I>, bias: MedianBias) -> IntervalTreeNode<I, K> {
+        let options = BuildOptions {
+            bias,
+            ..BuildOptions::new(natural_cmp)
+        };
+        IntervalTreeNode::build(intervals, &options).0
+    }
+
+    /// Creates an IntervalTreeNode given a collection of intervals, with at most max_depth edges
+    /// between the root and any node (i.e. height() <= max_depth + 1), to bound the query time.
+    /// Once max_depth is reached, all the remaining intervals are put in a single leaf, which is
+    /// checked linearly by the queries.
+    pub fn from_intervals_max_depth(intervals: Vec<I>, max_depth: usize) -> IntervalTreeNode<I, K> {
+        let options = BuildOptions {
+            max_depth: Some(max_depth),
+            ..BuildOptions::new(natural_cmp)
+        };
+        IntervalTreeNode::build(intervals, &options).0
+    }
+
+    /// Same as from_intervals, skipping the checks of the input: the dimensions of the intervals
+    /// are not compared, and incomparable scalars (i.e. NaN's) are considered equal instead of
+    /// panicking.
+    /// The caller has to guarantee the input is well formed: intervals of different dimensions
+    /// or with NaN bounds yield an invalid tree, whose queries return wrong results (or panic
+    /// when indexing the smaller intervals).
+    pub fn from_intervals_unchecked(intervals: Vec<I>) -> IntervalTreeNode<I, K> {
+        let options = BuildOptions {
+            check_dimensions: false,
+            ..BuildOptions::new(|a: I::Scalar, b| a.partial_cmp(&b).unwrap_or(Ordering::Equal))
+        };
+        IntervalTreeNode::build(intervals, &options).0
+    }
+
+    /// Creates an IntervalTreeNode given a collection of intervals, splitting the nodes where most
+    /// intervals straddle the median of their averages (e.g. intervals sharing the same center,
+    /// with different widths) at the median of their bounds instead.
+    /// This yields more balanced trees with smaller leaves for such inputs, at the cost of a
+    /// slower construction.
+    pub fn from_intervals_tie_aware(intervals: Vec<I>) -> IntervalTreeNode<I, K> {
+        let options = BuildOptions {
+            tie_aware: true,
+            ..BuildOptions::new(natural_cmp)
+        };
+        IntervalTreeNode::build(intervals, &options).0
+    }
+
+    /// Creates an IntervalTreeNode given a collection of intervals, splitting on the dimensions in
+    /// the given order (a permutation of 0..K) instead of the natural one. Splitting first on the
+    /// most discriminative dimension gives shallower trees for anisotropic data.
+    /// The queries follow the order stored in the nodes.
+    pub fn from_intervals_order(intervals: Vec<I>, order: [usize; K]) -> IntervalTreeNode<I, K> {
+        let mut sorted = order;
+        sorted.sort_unstable();
+        assert!(
+            sorted.iter().enumerate().all(|(k, &o)| k == o),
+            "The order should be a permutation of the dimensions!"
+        );
+        let options = BuildOptions {
+            order: order.to_vec(),
+            ..BuildOptions::new(natural_cmp)
+        };
+        IntervalTreeNode::build(intervals, &options).0
+    }
+
+    /// Creates an IntervalTreeNode given a collection of intervals, calling observer with the
+    /// description of each node once it is built (i.e. after its descendants), e.g. to study the
+    /// dynamics of the construction.
+    pub fn from_intervals_observed<F: FnMut(NodeBuildEvent<I::Scalar>)>(
+        intervals: Vec<I>,
+        mut observer: F,
+    ) -> IntervalTreeNode<I, K> {
+        IntervalTreeNode::build_observed(intervals, &BuildOptions::new(natural_cmp), &mut observer)
+            .0
+    }
+
+    fn build<C: Fn(I::Scalar, I::Scalar) -> Ordering>(
+        intervals: Vec<I>,
+        options: &BuildOptions<C>,
+    ) -> (IntervalTreeNode<I, K>, BuildReport) {
+        IntervalTreeNode::build_observed(intervals, options, &mut |_| {})
+    }
+
+    fn build_observed<C: Fn(I::Scalar, I::Scalar) -> Ordering>(
+        mut intervals: Vec<I>,
+        options: &BuildOptions<C>,
+        observer: &mut dyn FnMut(NodeBuildEvent<I::Scalar>),
+    ) -> (IntervalTreeNode<I, K>, BuildReport) {
+        let k = options.first_dimension();
+        sort_at(&mut intervals, k, &options.cmp);
+        let mut report = BuildReport::default();
+        let tree = IntervalTreeNode::from_intervals_rec(
+            intervals,
+            k,
+            1,
+            options,
+            &mut report,
+            observer,
+            &|intervals, k| sort_at(intervals, k, &options.cmp),
+        );
+        (tree, report)
+    }
+
+    /// Returns on which side of median the interval should go, w.r.t the build options.
+    fn partition_side<C: Fn(I::Scalar, I::Scalar) -> Ordering>(
+        i: &I,
+        k: usize,
+        median: I::Scalar,
+        options: &BuildOptions<C>,
+        bias: MedianBias,
+    ) -> Ordering {
+        let ordering = options.cmp_at(i, k, median);
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+        // Only the intervals ending (resp. starting) exactly at the median are moved
+        let (lo, hi) = options.bounds_at(i, k);
+        match bias {
+            MedianBias::Left if (options.cmp)(hi, median).is_eq() => Ordering::Less,
+            MedianBias::Right if (options.cmp)(lo, median).is_eq() => Ordering::Greater,
+            _ => ordering,
+        }
+    }
+
+    /// Returns whether all the intervals contain their median on the kth dimension, i.e. whether
+    /// they would all end up in the center of a node splitting on k.
+    /// This reorders the intervals.
+    fn straddle_median<C: Fn(I::Scalar, I::Scalar) -> Ordering>(
+        intervals: &mut [I],
+        k: usize,
+        options: &BuildOptions<C>,
+    ) -> bool {
+        let cmp = &options.cmp;
+        if intervals.is_empty() {
+            return true;
+        }
+        let mid = intervals.len() / 2;
+        let median = intervals
+            .select_nth_unstable_by(mid, |a, b| cmp(a.avg_at(k), b.avg_at(k)))
+            .1
+            .avg_at(k);
+        intervals
+            .iter()
+            .all(|i| options.cmp_at(i, k, median) == Ordering::Equal)
+    }
+
+    /// Expects the intervals to be sorted by `avg_at(k)`.
+    /// depth is the number of nodes from the root to the created one (included).
+    fn from_intervals_rec<C: Fn(I::Scalar, I::Scalar) -> Ordering>(
+        intervals: Vec<I>,
+        k: usize,
+        depth: usize,
+        options: &BuildOptions<C>,
+        report: &mut BuildReport,
+        observer: &mut dyn FnMut(NodeBuildEvent<I::Scalar>),
+        sort: &dyn Fn(&mut [I], usize),
+    ) -> IntervalTreeNode<I, K> {
+        report.nodes += 1;
+        report.max_depth = report.max_depth.max(depth);
+        assert!(
+            !intervals.is_empty(),
+            "Input intervals should not be empty!"
+        );
+        let mut median = intervals[intervals.len() / 2].avg_at(k);
+        let dimension = intervals[0].dimension();
+        if options.check_dimensions {
+            for i in &intervals {
+                assert!(
+                    i.dimension() == dimension,
+                    "Intervals need to have the same dimension when transformed into a tree!"
+                );
+            }
+        }
+
+        // Custom comparators may not be consistent with the bounds of the intervals
+        let bbox = if options.custom_cmp {
+            None
+        } else {
+            bounding_box(&intervals, dimension)
+        };
+
+        if options.max_depth.is_some_and(|max_depth| depth > max_depth) {
+            report.largest_leaf = report.largest_leaf.max(intervals.len());
+            observer(NodeBuildEvent {
+                depth,
+                k,
+                intervals: intervals.len(),
+                center_len: intervals.len(),
+                leaf: true,
+                center_val: median,
+            });
+            return IntervalTreeNode {
+                center_val: median,
+                k,
+                center: NodeContent::Leaf(leaf(intervals)),
+                lt_nodes: None,
+                gt_nodes: None,
+                bbox,
+            };
+        }
+
+        // The partitions are counted first, so each of them is allocated exactly once
+        let partition = |median, bias| {
+            let mut counts = [0; 3];
+            let sides = intervals
+                .iter()
+                .map(|i| {
+                    let side = IntervalTreeNode::partition_side(i, k, median, options, bias);
+                    counts[(side as i8 + 1) as usize] += 1;
+                    side
+                })
+                .collect::<Vec<_>>();
+            (sides, counts)
+        };
+        let (mut sides, mut counts) = partition(median, options.bias);
+        // A bias sending everything to the same child would recurse forever
+        if counts[0] == intervals.len() || counts[2] == intervals.len() {
+            (sides, counts) = partition(median, MedianBias::Center);
+        }
+
+        // Many intervals sharing the median average (e.g. nested intervals) all straddle it, while
+        // splitting at the median of their bounds still sends some of them to the children.
+        // Neither the interval ending (resp. starting) at that median can be sent to the lt
+        // (resp. gt) child, so the recursion always progresses.
+        if options.tie_aware && counts[1] > intervals.len() / 2 {
+            let bounds = intervals
+                .iter()
+                .map(|i| options.bounds_at(i, k))
+                .collect::<Vec<_>>();
+            let median_of = |mut values: Vec<I::Scalar>| {
+                let mid = values.len() / 2;
+                *values
+                    .select_nth_unstable_by(mid, |a, b| (options.cmp)(*a, *b))
+                    .1
+            };
+            let candidates = [
+                median_of(bounds.iter().map(|b| b.1).collect()),
+                median_of(bounds.iter().map(|b| b.0).collect()),
+            ];
+            for candidate in candidates {
+                let (candidate_sides, candidate_counts) = partition(candidate, MedianBias::Center);
+                if candidate_counts[1] < counts[1] {
+                    (median, sides, counts) = (candidate, candidate_sides, candidate_counts);
+                }
+            }
+        }
+
+        let total = intervals.len();
+        let mut lt_nodes = Vec::with_capacity(counts[0]);
+        let mut center = Vec::with_capacity(counts[1]);
+        let mut gt_nodes = Vec::with_capacity(counts[2]);
+        for (i, side) in intervals.into_iter().zip(sides) {
+            match side {
+                Ordering::Less => lt_nodes.push(i),
+                Ordering::Greater => gt_nodes.push(i),
+                Ordering::Equal => center.push(i),
+            }
+        }
+
+        let lt_nodes = if lt_nodes.is_empty() {
+            None
+        } else {
+            Some(Box::new(IntervalTreeNode::from_intervals_rec(
+                lt_nodes,
+                k,
+                depth + 1,
+                options,
+                report,
+                observer,
+                sort,
+            )))
+        };
+
+        let gt_nodes = if gt_nodes.is_empty() {
+            None
+        } else {
+            Some(Box::new(IntervalTreeNode::from_intervals_rec(
+                gt_nodes,
+                k,
+                depth + 1,
+                options,
+                report,
+                observer,
+                sort,
+            )))
+        };
+
+        // When no partition makes progress anymore (i.e. the remaining dimensions would only be a
+        // chain of nodes holding all the intervals in their center), the intervals are simply
+        // kept in a leaf
+        let no_progress = lt_nodes.is_none()
+            && gt_nodes.is_none()
+            && options
+                .dimensions_after(k, dimension)
+                .all(|k| IntervalTreeNode::straddle_median(&mut center, k, options));
+
+        // With a bias, the center can end up empty
+        let next_dimension = options.next_dimension(k, dimension);
+        let center =
+            if let Some(next_k) = next_dimension.filter(|_| !center.is_empty() && !no_progress) {
+                sort(&mut center, next_k);
+                NodeContent::Subtree(Box::new(IntervalTreeNode::from_intervals_rec(
+                    center,
+                    next_k,
+                    depth + 1,
+                    options,
+                    report,
+                    observer,
+                    sort,
+                )))
+            } else {
+                report.largest_leaf = report.largest_leaf.max(center.len());
+                // Without children, nothing was split away from these intervals
+                if lt_nodes.is_some() || gt_nodes.is_some() {
+                    report.straddling += center
+                        .iter()
+                        .filter(|i| {
+                            let (lo, hi) = options.bounds_at(*i, k);
+                            (options.cmp)(lo, median).is_lt() && (options.cmp)(hi, median).is_gt()
+                        })
+                        .count();
+                }
+                NodeContent::Leaf(leaf(center))
+            };
+
+        observer(NodeBuildEvent {
+            depth,
+            k,
+            intervals: total,
+            center_len: counts[1],
+            leaf: matches!(center, NodeContent::Leaf(_)),
+            center_val: median,
+        });
+
+        IntervalTreeNode {
+            center_val: median,
+            k,
+            center,
+            lt_nodes,
+            gt_nodes,
+            bbox,
+        }
+    }
+
+    /// Returns an interator over all intervals in the tree.
+    /// The order is stable, i.e. the same for a given input to the construction: for each node,
+    /// the intervals of lt_nodes come first, then the ones of the center (recursively, in the same
+    /// order for center subtrees), then the ones of gt_nodes. Intervals of the same leaf are
+    /// yielded in the order in which the construction stored them. All the other traversals
+    /// documented as following the order of iter (e.g. leaf_groups) rely on this.
+    /// The iterator is an ExactSizeIterator: the intervals are only counted (walking the nodes
+    /// once, see len) the first time its len or size_hint is asked for.
+    pub fn iter(&self) -> IntervalTreeIterator<'_, I, K> {
+        IntervalTreeIterator::new(self)
+    }
+
+    /// Returns the intervals of the tree grouped by leaf, in the same order as iter.
+    /// Intervals of the same leaf are spatially close to each other.
+    pub fn leaf_groups(&self) -> Vec<Vec<&I>> {
+        let mut groups = Vec::new();
+        self.for_each_leaf(&mut |leaf| groups.push(leaf.iter().collect()));
+        groups
+    }
+
+    /// Returns the slices of intervals stored in the non-empty leaves, in the same order as iter,
+    /// e.g. for bulk processing or exporting them without copies.
+    pub fn leaf_slices(&self) -> impl Iterator<Item = &[I]> {
+        let mut slices = Vec::new();
+        self.for_each_leaf(&mut |leaf| {
+            if !leaf.is_empty() {
+                slices.push(leaf);
+            }
+        });
+        slices.into_iter()
+    }
+
+    /// Returns the (at most) k leaves holding the most intervals, along with their number of
+    /// intervals, by decreasing size. Leaves of the same size are returned in the order of iter.
+    pub fn densest_leaves(&self, k: usize) -> Vec<(&[I], usize)> {
+        // Min-heap of the k densest leaves so far, the latest leaf being the smallest on ties
+        let mut heap = BinaryHeap::with_capacity(k + 1);
+        let mut seq = 0;
+        self.for_each_leaf(&mut |leaf| {
+            heap.push(Reverse((leaf.len(), Reverse(seq), LeafRef(leaf))));
+            if heap.len() > k {
+                heap.pop();
+            }
+            seq += 1;
+        });
+        heap.into_sorted_vec()
+            .into_iter()
+            .map(|Reverse((len, _, LeafRef(leaf)))| (leaf, len))
+            .collect()
+    }
+
+    /// Calls f on every leaf of the tree, in the same order as iter.
+    fn for_each_leaf<'a, F: FnMut(&'a [I])>(&'a self, f: &mut F) {
+        if let Some(lt) = &self.lt_nodes {
+            lt.for_each_leaf(f);
+        }
+        match &self.center {
+            NodeContent::Subtree(n) => n.for_each_leaf(f),
+            NodeContent::Leaf(intervals) => f(intervals),
+        }
+        if let Some(gt) = &self.gt_nodes {
+            gt.for_each_leaf(f);
+        }
+    }
+
+    /// Returns a recursive description of the structure of the tree, mostly useful for debugging.
+    pub fn to_structure(&self) -> TreeNodeInfo<I::Scalar> {
+        self.to_structure_rec().0
+    }
+
+    /// Also returns the number of intervals in the subtree, to avoid recounting them.
+    fn to_structure_rec(&self) -> (TreeNodeInfo<I::Scalar>, usize) {
+        let (center, center_len) = match &self.center {
+            NodeContent::Subtree(n) => {
+                let (info, len) = n.to_structure_rec();
+                (Some(Box::new(info)), len)
+            }
+            NodeContent::Leaf(intervals) => (None, intervals.len()),
+        };
+        let mut len = center_len;
+        let mut child = |n: &Option<Box<IntervalTreeNode<I, K>>>| {
+            n.as_ref().map(|n| {
+                let (info, child_len) = n.to_structure_rec();
+                len += child_len;
+                Box::new(info)
+            })
+        };
+        let lt_nodes = child(&self.lt_nodes);
+        let gt_nodes = child(&self.gt_nodes);
+        let info = TreeNodeInfo {
+            k: self.k,
+            center_val: self.center_val,
+            center_len,
+            center,
+            lt_nodes,
+            gt_nodes,
+        };
+        (info, len)
+    }
+
+    /// Returns a summary of the shape of the tree.
+    pub fn stats(&self) -> TreeStats {
+        let mut stats = match &self.center {
+            NodeContent::Subtree(n) => n.stats(),
+            NodeContent::Leaf(intervals) => TreeStats {
+                leaves: 1,
+                intervals: intervals.len(),
+                leaf_capacity: intervals.capacity(),
+                ..Default::default()
+            },
+        };
+        for child in [&self.lt_nodes, &self.gt_nodes].into_iter().flatten() {
+            let child = child.stats();
+            stats.nodes += child.nodes;
+            stats.leaves += child.leaves;
+            stats.intervals += child.intervals;
+            stats.height = stats.height.max(child.height);
+            stats.leaf_capacity += child.leaf_capacity;
+        }
+        stats.nodes += 1;
+        stats.height += 1;
+        stats
+    }
+
+    /// Returns the number of intervals stored in the tree.
+    /// This is not cached, but only walks the nodes of the tree (not the intervals themselves).
+    pub fn len(&self) -> usize {
+        self.stats().intervals
+    }
+
+    /// Returns whether the tree does not contain any interval (e.g. after retain).
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of nodes on the longest path from the root to a leaf.
+    pub fn height(&self) -> usize {
+        self.stats().height
+    }
+
+    /// Returns an estimate of the memory used by the tree, in bytes: the nodes themselves (the
+    /// root included), and the heap allocations of the leaves and bounding boxes.
+    /// The overhead of the allocator, and the memory owned by the intervals themselves (e.g. if
+    /// they contain a Vec) are not accounted for.
+    pub fn memory_footprint(&self) -> usize {
+        std::mem::size_of::<Self>() + self.heap_footprint()
+    }
+
+    /// Same as memory_footprint, excluding self.
+    fn heap_footprint(&self) -> usize {
+        let center = match &self.center {
+            NodeContent::Subtree(n) => n.memory_footprint(),
+            NodeContent::Leaf(intervals) => leaf_heap_bytes(intervals),
+        };
+        let bbox = self.bbox.as_ref().map_or(0, |bbox| {
+            bbox.capacity() * std::mem::size_of::<(I::Scalar, I::Scalar)>()
+        });
+        center
+            + bbox
+            + [&self.lt_nodes, &self.gt_nodes]
+                .into_iter()
+                .flatten()
+                .map(|n| n.memory_footprint())
+                .sum::<usize>()
+    }
+
+    /// Returns the number of nodes whose center is a subtree (on the next dimension), i.e. how
+    /// often the traversal has to switch from a dimension to the next one.
+    pub fn dimension_transition_count(&self) -> usize {
+        let center = match &self.center {
+            NodeContent::Subtree(n) => 1 + n.dimension_transition_count(),
+            NodeContent::Leaf(_) => 0,
+        };
+        center
+            + [&self.lt_nodes, &self.gt_nodes]
+                .into_iter()
+                .flatten()
+                .map(|n| n.dimension_transition_count())
+                .sum::<usize>()
+    }
+
+    /// Checks the structure of the tree, e.g. after loading it from an untrusted source: all the
+    /// intervals of the lt (resp. gt) child of a node should end before (resp. start after) its
+    /// center_val on its dimension, and all intervals should have the same dimension. Queries
+    /// are only correct for valid trees.
+    /// Intervals touching center_val are allowed in the children, as done by
+    /// from_intervals_biased. Trees built with from_intervals_by are checked w.r.t the natural
+    /// order of the scalars, and may thus be reported as invalid.
+    pub fn validate_invariants(&self) -> Result<(), InvariantError> {
+        let dimension = match self.iter().next() {
+            Some(i) => i.dimension(),
+            None => return Ok(()),
+        };
+        for i in self.iter() {
+            if i.dimension() != dimension {
+                return Err(InvariantError::DimensionMismatch {
+                    expected: dimension,
+                    found: i.dimension(),
+                });
+            }
+        }
+        if self.k >= dimension {
+            return Err(InvariantError::InvalidSplitDimension {
+                expected: 0,
+                found: self.k,
+            });
+        }
+        self.validate_invariants_rec(self.k, dimension, &mut vec![false; dimension])
+    }
+
+    /// split tells which dimensions the ancestors of self split their centers on (e.g.
+    /// from_intervals_order), none of which can be split on again by the center subtrees.
+    fn validate_invariants_rec(
+        &self,
+        k: usize,
+        dimension: usize,
+        split: &mut [bool],
+    ) -> Result<(), InvariantError> {
+        if self.k != k {
+            return Err(InvariantError::InvalidSplitDimension {
+                expected: k,
+                found: self.k,
+            });
+        }
+        if let NodeContent::Subtree(n) = &self.center {
+            split[k] = true;
+            if n.k >= dimension || split[n.k] {
+                let expected = split.iter().position(|s| !s).unwrap_or(dimension);
+                return Err(InvariantError::InvalidSplitDimension {
+                    expected,
+                    found: n.k,
+                });
+            }
+            let result = n.validate_invariants_rec(n.k, dimension, split);
+            split[k] = false;
+            result?;
+        }
+
+        let children = [
+            (&self.lt_nodes, Ordering::Less),
+            (&self.gt_nodes, Ordering::Greater),
+        ];
+        for (child, side) in children {
+            if let Some(n) = child {
+                let misplaced = n.iter().any(|i| match side {
+                    Ordering::Less => i.max_at(k) > self.center_val,
+                    _ => i.min_at(k) < self.center_val,
+                });
+                if misplaced {
+                    return Err(InvariantError::MisplacedInterval { k, side });
+                }
+                n.validate_invariants_rec(k, dimension, split)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Inserts an interval in the tree, without rebalancing it: the interval is put in the first
+    /// node (w.r.t the dimension of that node) it straddles, or in a new leaf node when it lies
+    /// on a side of a node without child on that side.
+    /// Many insertions can thus unbalance the tree: see ManagedIntervalTree.
+    pub fn insert(&mut self, interval: I) {
+        self.insert_rec(interval, 1);
+    }
+
+    /// Returns the depth (number of nodes from the root, included) of the node the interval was
+    /// inserted in.
+    pub(crate) fn insert_rec(&mut self, interval: I, depth: usize) -> usize {
+        if let Some(bbox) = &self.bbox {
+            // Wrapping intervals make the box unknown
+            self.bbox = bounding_box(std::slice::from_ref(&interval), bbox.len()).map(|b| {
+                bbox.iter()
+                    .zip(b)
+                    .map(|(&(lo, hi), (i_lo, i_hi))| (min(lo, i_lo), max(hi, i_hi)))
+                    .collect()
+            });
+        }
+        let k = self.k;
+        let child = match interval.cmp_at(k, self.center_val) {
+            Ordering::Less => &mut self.lt_nodes,
+            Ordering::Greater => &mut self.gt_nodes,
+            Ordering::Equal => {
+                return match &mut self.center {
+                    NodeContent::Subtree(n) => n.insert_rec(interval, depth + 1),
+                    NodeContent::Leaf(intervals) => {
+                        intervals.push(interval);
+                        depth
+                    }
+                }
+            }
+        };
+        match child {
+            Some(n) => n.insert_rec(interval, depth + 1),
+            None => {
+                *child = Some(Box::new(IntervalTreeNode {
+                    center_val: interval.avg_at(k),
+                    k,
+                    bbox: bounding_box(std::slice::from_ref(&interval), interval.dimension()),
+                    center: NodeContent::Leaf(leaf(vec![interval])),
+                    lt_nodes: None,
+                    gt_nodes: None,
+                }));
+                depth + 1
+            }
+        }
+    }
+
+    /// Same as range_search, pairing each match with its handle, to remove it later on with
+    /// remove_handle.
+    pub fn range_search_handles<II: Interval<K, Scalar = I::Scalar>>(
+        &self,
+        x: &II,
+    ) -> Vec<(IntervalHandle, &I)> {
+        let mut visitor = HandlesVisitor {
+            x,
+            path: Vec::new(),
+            out: Vec::new(),
+        };
+        let _ = self.visit(x, &mut visitor);
+        visitor.out
+    }
+
+    /// Removes the interval at the given handle (as returned by range_search_handles), and returns
+    /// it (None if the handle does not point to any interval of the tree).
+    /// The other intervals of its leaf keep their order, so that the handles to the intervals
+    /// before it stay valid: remove them in decreasing index order.
+    /// As with remove, the structure of the tree is left untouched.
+    pub fn remove_handle(&mut self, handle: &IntervalHandle) -> Option<I> {
+        let mut node = self;
+        for step in &handle.path {
+            node = match step {
+                PathStep::Lt => node.lt_nodes.as_deref_mut()?,
+                PathStep::Gt => node.gt_nodes.as_deref_mut()?,
+                PathStep::Center => match &mut node.center {
+                    NodeContent::Subtree(n) => n,
+                    NodeContent::Leaf(_) => return None,
+                },
+            };
+        }
+        match &mut node.center {
+            NodeContent::Leaf(intervals) if handle.index < intervals.len() => {
+                Some(intervals.remove(handle.index))
+            }
+            _ => None,
+        }
+    }
+
+    /// Removes an interval equal to the given one from the tree, and returns it (None if the tree
+    /// does not contain such an interval).
+    /// As with retain, the structure of the tree is left untouched.
+    pub fn remove(&mut self, interval: &I) -> Option<I>
+    where
+        I: PartialEq,
+    {
+        let removed = match &mut self.center {
+            NodeContent::Subtree(n) => n.remove(interval),
+            NodeContent::Leaf(intervals) => intervals
+                .iter()
+                .position(|i| i == interval)
+                .map(|index| intervals.swap_remove(index)),
+        };
+        if removed.is_some() {
+            return removed;
+        }
+
+        // Same traversal as range_search, as biased trees can store intervals touching
+        // center_val in the children
+        if self.reaches(interval, PathStep::Lt) {
+            if let Some(removed) = self.lt_nodes.as_mut().and_then(|n| n.remove(interval)) {
+                return Some(removed);
+            }
+        }
+        if self.reaches(interval, PathStep::Gt) {
+            return self.gt_nodes.as_mut().and_then(|n| n.remove(interval));
+        }
+        None
+    }
+
+    /// Retains only the intervals for which f returns true.
+    /// The structure of the tree is left untouched, even if some nodes end up empty: see compact.
+    pub fn retain<F: FnMut(&I) -> bool>(&mut self, mut f: F) {
+        self.retain_rec(&mut f);
+    }
+
+    fn retain_rec<F: FnMut(&I) -> bool>(&mut self, f: &mut F) {
+        match &mut self.center {
+            NodeContent::Subtree(n) => n.retain_rec(f),
+            NodeContent::Leaf(intervals) => intervals.retain(|i| f(i)),
+        }
+        for child in [&mut self.lt_nodes, &mut self.gt_nodes]
+            .into_iter()
+            .flatten()
+        {
+            child.retain_rec(f);
+        }
+    }
+
+    /// Releases the memory left unused after removals: shrinks every leaf to fit its intervals,
+    /// and drops the nodes which do not contain any interval anymore.
+    /// This does not rebalance the tree: the remaining nodes keep their split values, and the
+    /// root node is always kept, even when the whole tree is empty.
+    pub fn compact(&mut self) {
+        self.compact_rec();
+    }
+
+    /// Returns whether the node is empty, and can be dropped by its parent.
+    fn compact_rec(&mut self) -> bool {
+        for child in [&mut self.lt_nodes, &mut self.gt_nodes] {
+            if child.as_mut().is_some_and(|n| n.compact_rec()) {
+                *child = None;
+            }
+        }
+
+        let empty_center = match &mut self.center {
+            NodeContent::Subtree(n) => n.compact_rec(),
+            NodeContent::Leaf(intervals) => {
+                intervals.shrink_to_fit();
+                intervals.is_empty()
+            }
+        };
+        if empty_center {
+            self.center = NodeContent::Leaf(LeafIntervals::new());
+        }
+        empty_center && self.lt_nodes.is_none() && self.gt_nodes.is_none()
+    }
+
+    /// Recomputes the splits which no longer reflect the intervals of the tree, e.g. after
+    /// removing many of them with retain, then drops the empty nodes (see compact).
+    /// The nodes whose lt or gt subtree holds more than half of their intervals (which a median
+    /// split never does) get their center_val recomputed as the median of the avg_at of their
+    /// remaining intervals, and only the intervals lying on the wrong side of that new split are
+    /// moved (as with insert) between their center and children. Contrary to a full rebuild (see
+    /// ManagedIntervalTree::rebalance), the balanced nodes and the split dimensions are kept as
+    /// they are, and nothing is re-sorted.
+    /// As with insert, the intervals are routed with their own cmp_at: this is not meant for
+    /// trees built with a custom comparator (see from_intervals_by), and the moved intervals can
+    /// create nodes deeper than the max_depth the tree was built with.
+    pub fn refresh_splits(&mut self) {
+        self.refresh_splits_rec();
+        self.compact();
+    }
+
+    /// Returns the number of intervals of the subtree, the sizes being computed bottom-up so that
+    /// each node is only counted once.
+    fn refresh_splits_rec(&mut self) -> usize {
+        let center_len = match &mut self.center {
+            NodeContent::Subtree(n) => n.refresh_splits_rec(),
+            NodeContent::Leaf(intervals) => intervals.len(),
+        };
+        let lt_len = self.lt_nodes.as_mut().map_or(0, |n| n.refresh_splits_rec());
+        let gt_len = self.gt_nodes.as_mut().map_or(0, |n| n.refresh_splits_rec());
+        let len = center_len + lt_len + gt_len;
+        if lt_len.max(gt_len) <= len / 2 + 1 {
+            return len;
+        }
+
+        let k = self.k;
+        let mut averages = self.iter().map(|i| i.avg_at(k)).collect::<Vec<_>>();
+        let mid = averages.len() / 2;
+        let center_val = *averages
+            .select_nth_unstable_by(mid, |a, b| natural_cmp(*a, *b))
+            .1;
+        self.center_val = center_val;
+
+        let mut moved = Vec::new();
+        let mut off_center = |i: &I| i.cmp_at(k, center_val).is_ne();
+        match &mut self.center {
+            NodeContent::Subtree(n) => n.extract_rec(&mut off_center, &mut moved),
+            NodeContent::Leaf(intervals) => extract(intervals, &mut off_center, &mut moved),
+        }
+        if let Some(n) = &mut self.lt_nodes {
+            n.extract_rec(&mut |i| !i.cmp_at(k, center_val).is_lt(), &mut moved);
+        }
+        if let Some(n) = &mut self.gt_nodes {
+            n.extract_rec(&mut |i| !i.cmp_at(k, center_val).is_gt(), &mut moved);
+        }
+
+        // Only the parts of the node which received intervals can have drifted in turn
+        let mut received = [false; 3];
+        for i in moved {
+            received[(i.cmp_at(k, center_val) as i8 + 1) as usize] = true;
+            self.insert_rec(i, 1);
+        }
+        if received[0] {
+            if let Some(n) = &mut self.lt_nodes {
+                n.refresh_splits_rec();
+            }
+        }
+        if received[1] {
+            if let NodeContent::Subtree(n) = &mut self.center {
+                n.refresh_splits_rec();
+            }
+        }
+        if received[2] {
+            if let Some(n) = &mut self.gt_nodes {
+                n.refresh_splits_rec();
+            }
+        }
+        len
+    }
+
+    /// Moves the intervals of the subtree for which f returns true to out, keeping the structure
+    /// of the tree as is.
+    fn extract_rec<F: FnMut(&I) -> bool>(&mut self, f: &mut F, out: &mut Vec<I>) {
+        match &mut self.center {
+            NodeContent::Subtree(n) => n.extract_rec(f, out),
+            NodeContent::Leaf(intervals) => extract(intervals, f, out),
+        }
+        for child in [&mut self.lt_nodes, &mut self.gt_nodes]
+            .into_iter()
+            .flatten()
+        {
+            child.extract_rec(f, out);
+        }
+    }
+
+    /// Collapses the nodes with an empty center leaf and a single child into that child, to
+    /// reduce pointer-chasing (e.g. after retain and compact).
+    /// The split value of a collapsed node was only used to skip its child, so queries stay
+    /// correct: they just cannot skip it anymore.
+    pub fn prune_empty(&mut self) {
+        for child in [&mut self.lt_nodes, &mut self.gt_nodes]
+            .into_iter()
+            .flatten()
+        {
+            child.prune_empty();
+        }
+        if let NodeContent::Subtree(n) = &mut self.center {
+            n.prune_empty();
+        }
+
+        // The child was already pruned, so it cannot be collapsed itself
+        let empty_center =
+            matches!(&self.center, NodeContent::Leaf(intervals) if intervals.is_empty());
+        if empty_center {
+            match (self.lt_nodes.take(), self.gt_nodes.take()) {
+                (Some(child), None) | (None, Some(child)) => *self = *child,
+                (lt_nodes, gt_nodes) => {
+                    self.lt_nodes = lt_nodes;
+                    self.gt_nodes = gt_nodes;
+                }
+            }
+        }
+    }
+
+    /// Consumes the tree, returning all of its intervals (in the same order as iter).
+    pub fn into_intervals(self) -> Vec<I> {
+        let mut intervals = Vec::new();
+        self.into_intervals_rec(&mut intervals);
+        intervals
+    }
+
+    fn into_intervals_rec(self, out: &mut Vec<I>) {
+        if let Some(lt) = self.lt_nodes {
+            lt.into_intervals_rec(out);
+        }
+        match self.center {
+            NodeContent::Subtree(n) => n.into_intervals_rec(out),
+            NodeContent::Leaf(intervals) => out.extend(intervals),
+        }
+        if let Some(gt) = self.gt_nodes {
+            gt.into_intervals_rec(out);
+        }
+    }
+
+    /// Transforms every interval of the tree with f, keeping the structure of the tree as is.
+    /// f has to preserve the bounds of the intervals (at least on the split dimensions),
+    /// otherwise the resulting tree is invalid and queries will return wrong results.
+    pub fn map<J: Interval<K, Scalar = I::Scalar>, F: FnMut(I) -> J>(
+        self,
+        mut f: F,
+    ) -> IntervalTreeNode<J, K> {
+        // f only has to preserve the bounds on the split dimensions
+        self.map_rec(&mut f, false)
+    }
+
+    /// Same as map, keeping the bounding boxes of the nodes if keep_bbox is set, i.e. if f
+    /// preserves the bounds on every dimension.
+    fn map_rec<J: Interval<K, Scalar = I::Scalar>, F: FnMut(I) -> J>(
+        self,
+        f: &mut F,
+        keep_bbox: bool,
+    ) -> IntervalTreeNode<J, K> {
+        IntervalTreeNode {
+            center: match self.center {
+                NodeContent::Subtree(n) => NodeContent::Subtree(Box::new(n.map_rec(f, keep_bbox))),
+                NodeContent::Leaf(intervals) => {
+                    NodeContent::Leaf(intervals.into_iter().map(&mut *f).collect())
+                }
+            },
+            center_val: self.center_val,
+            k: self.k,
+            lt_nodes: self.lt_nodes.map(|n| Box::new(n.map_rec(f, keep_bbox))),
+            gt_nodes: self.gt_nodes.map(|n| Box::new(n.map_rec(f, keep_bbox))),
+            bbox: self.bbox.filter(|_| keep_bbox),
+        }
+    }
+
+    /// Merges two trees into one containing the intervals of both.
+    /// This currently rebuilds the whole tree from scratch, i.e. costs as much as from_intervals
+    /// on the combined intervals.
+    /// If other is empty (e.g. after a retain removing everything), self is returned as is, so
+    /// that merging two empty trees gives an empty tree.
+    /// TODO: merge the structures instead of rebuilding
+    pub fn merge(self, other: IntervalTreeNode<I, K>) -> IntervalTreeNode<I, K> {
+        let other = other.into_intervals();
+        if other.is_empty() {
+            return self;
+        }
+        let mut intervals = self.into_intervals();
+        if let (Some(a), Some(b)) = (intervals.first(), other.first()) {
+            assert!(
+                a.dimension() == b.dimension(),
+                "Merged trees need to have the same dimension!"
+            );
+        }
+        intervals.extend(other);
+        IntervalTreeNode::from_intervals(intervals)
+    }
+}
+
+/// Semantic equality: two trees are equal if they contain the same intervals, the same number of
+/// times each, regardless of their structure (which depends on the input order).
+/// This costs O(n^2) comparisons, as I only needs to be PartialEq.
+impl<const K: usize, I: Interval<K> + PartialEq> PartialEq for IntervalTreeNode<I, K> {
+    fn eq(&self, other: &Self) -> bool {
+        let mut unmatched = other.iter().collect::<Vec<_>>();
+        for i in self.iter() {
+            match unmatched.iter().position(|o| i == *o) {
+                Some(pos) => {
+                    unmatched.swap_remove(pos);
+                }
+                None => return false,
+            }
+        }
+        unmatched.is_empty()
+    }
+}
+
+impl<const K: usize, I: Interval<K> + Eq> Eq for IntervalTreeNode<I, K> {}
+
+/// Consistent with PartialEq: the hash only depends on the multiset of stored intervals, and not
+/// on the structure of the tree (nor on center_val, as float scalars are not Hash).
+/// Each interval is hashed on its own, and the results are combined in an order-independent way.
+impl<const K: usize, I: Interval<K> + Hash> Hash for IntervalTreeNode<I, K> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let mut len = 0usize;
+        let mut combined = 0u64;
+        for i in self.iter() {
+            let mut hasher = DefaultHasher::new();
+            i.hash(&mut hasher);
+            combined = combined.wrapping_add(hasher.finish());
+            len += 1;
+        }
+        len.hash(state);
+        combined.hash(state);
+    }
+}
+
+/// Query interval compared w.r.t cmp, see range_search_by.
+struct ByCmp<'a, II, C> {
+    inner: &'a II,
+    cmp: C,
+}
+
+impl<const K: usize, S: Scalar, II: Interval<K, Scalar = S>, C: Fn(S, S) -> Ordering> Interval<K>
+    for ByCmp<'_, II, C>
+{
+    type Scalar = S;
+    fn min_at(&self, k: usize) -> S {
+        self.inner.min_at(k)
+    }
+
+    fn max_at(&self, k: usize) -> S {
+        self.inner.max_at(k)
+    }
+
+    fn overlaps_at<III: Interval<K, Scalar = S>>(&self, k: usize, o: &III) -> bool {
+        let (lo, hi) = bounds_at_by(self.inner, k, &self.cmp);
+        let (o_lo, o_hi) = bounds_at_by(o, k, &self.cmp);
+        (self.cmp)(lo, o_hi) != Ordering::Greater && (self.cmp)(o_lo, hi) != Ordering::Greater
+    }
+
+    fn dimension(&self) -> usize {
+        self.inner.dimension()
+    }
+
+    fn cmp_at(&self, k: usize, s: S) -> Ordering {
+        cmp_at_by(self.inner, k, s, &self.cmp)
+    }
+}
+
+/// Query interval only bounded on the given axes, see range_search_axes.
+struct OnAxes<'a, II> {
+    inner: &'a II,
+    axes: &'a [usize],
+}
+
+impl<const K: usize, S: Scalar, II: Interval<K, Scalar = S>> Interval<K> for OnAxes<'_, II> {
+    type Scalar = S;
+    fn min_at(&self, k: usize) -> S {
+        self.inner.min_at(k)
+    }
+
+    fn max_at(&self, k: usize) -> S {
+        self.inner.max_at(k)
+    }
+
+    fn overlaps_at<III: Interval<K, Scalar = S>>(&self, k: usize, o: &III) -> bool {
+        !self.axes.contains(&k) || self.inner.overlaps_at(k, o)
+    }
+
+    fn dimension(&self) -> usize {
+        self.inner.dimension()
+    }
+
+    fn cmp_at(&self, k: usize, s: S) -> Ordering {
+        if self.axes.contains(&k) {
+            self.inner.cmp_at(k, s)
+        } else {
+            Ordering::Equal
+        }
+    }
+}
+
+/// Visitor of range_search_by_depth, tracking the depth of the current node.
+struct DepthVisitor<'a, 'b, I, II> {
+    x: &'b II,
+    depth: usize,
+    out: Vec<(usize, &'a I)>,
+}
+
+impl<'a, const K: usize, I: Interval<K>, II: Interval<K, Scalar = I::Scalar>> Visitor<'a, I, K>
+    for DepthVisitor<'a, '_, I, II>
+{
+    type Break = Infallible;
+    fn leaf(&mut self, intervals: &'a LeafIntervals<I>) -> ControlFlow<Infallible> {
+        let (x, depth) = (self.x, self.depth);
+        self.out.extend(
+            intervals
+                .iter()
+                .filter(|i| i.overlaps(x))
+                .map(|i| (depth, i)),
+        );
+        ControlFlow::Continue(())
+    }
+
+    fn descend(&mut self, _step: PathStep) {
+        self.depth += 1;
+    }
+
+    fn ascend(&mut self) {
+        self.depth -= 1;
+    }
+}
+
+/// Visitor of query_heatmap, tracking the path to the current node.
+struct HeatmapVisitor<'b, II> {
+    x: &'b II,
+    path: Vec<PathStep>,
+    report: &'b mut HeatmapReport,
+}
+
+impl<'a, const K: usize, I: Interval<K> + 'a, II: Interval<K, Scalar = I::Scalar>> Visitor<'a, I, K>
+    for HeatmapVisitor<'_, II>
+{
+    type Break = Infallible;
+    fn node(&mut self) -> ControlFlow<Infallible> {
+        self.report
+            .nodes
+            .entry(self.path.clone())
+            .or_default()
+            .visits += 1;
+        ControlFlow::Continue(())
+    }
+
+    fn leaf(&mut self, intervals: &'a LeafIntervals<I>) -> ControlFlow<Infallible> {
+        let matches = intervals.iter().filter(|i| i.overlaps(self.x)).count();
+        self.report
+            .nodes
+            .entry(self.path.clone())
+            .or_default()
+            .matches += matches;
+        ControlFlow::Continue(())
+    }
+
+    fn descend(&mut self, step: PathStep) {
+        self.path.push(step);
+    }
+
+    fn ascend(&mut self) {
+        self.path.pop();
+    }
+}
+
+/// Visitor of range_search_cancellable, checking cancel at every node.
+struct CancellableVisitor<'a, 'b, I, II> {
+    x: &'b II,
+    cancel: &'b AtomicBool,
+    out: Vec<&'a I>,
+}
+
+impl<'a, const K: usize, I: Interval<K>, II: Interval<K, Scalar = I::Scalar>> Visitor<'a, I, K>
+    for CancellableVisitor<'a, '_, I, II>
+{
+    type Break = ();
+    fn node(&mut self) -> ControlFlow<()> {
+        if self.cancel.load(AtomicOrdering::Relaxed) {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    }
+
+    fn leaf(&mut self, intervals: &'a LeafIntervals<I>) -> ControlFlow<()> {
+        let x = self.x;
+        self.out.extend(intervals.iter().filter(|i| i.overlaps(x)));
+        ControlFlow::Continue(())
+    }
+}
+
+/// Visitor of range_search_handles, tracking the path to the current node.
+struct HandlesVisitor<'a, 'b, I, II> {
+    x: &'b II,
+    path: Vec<PathStep>,
+    out: Vec<(IntervalHandle, &'a I)>,
+}
+
+impl<'a, const K: usize, I: Interval<K>, II: Interval<K, Scalar = I::Scalar>> Visitor<'a, I, K>
+    for HandlesVisitor<'a, '_, I, II>
+{
+    type Break = Infallible;
+    fn leaf(&mut self, intervals: &'a LeafIntervals<I>) -> ControlFlow<Infallible> {
+        let (x, path) = (self.x, &self.path);
+        self.out.extend(
+            intervals
+                .iter()
+                .enumerate()
+                .filter(|(_, i)| i.overlaps(x))
+                .map(|(index, i)| {
+                    let handle = IntervalHandle {
+                        path: path.clone(),
+                        index,
+                    };
+                    (handle, i)
+                }),
+        );
+        ControlFlow::Continue(())
+    }
+
+    fn descend(&mut self, step: PathStep) {
+        self.path.push(step);
+    }
+
+    fn ascend(&mut self) {
+        self.path.pop();
+    }
+}
+
+/// Query interval inflated by a margin on every dimension, see range_search_expanded.
+struct Expanded<'a, II, S> {
+    inner: &'a II,
+    margin: S,
+}
+
+impl<const K: usize, S: Scalar, II: Interval<K, Scalar = S>> Interval<K> for Expanded<'_, II, S> {
+    type Scalar = S;
+    fn min_at(&self, k: usize) -> S {
+        self.inner.min_at(k) - self.margin
+    }
+
+    fn max_at(&self, k: usize) -> S {
+        self.inner.max_at(k) + self.margin
+    }
+
+    fn dimension(&self) -> usize {
+        self.inner.dimension()
+    }
+}
+
+/// Query interval whose bounds are converted to another scalar type, see range_search_convert.
+struct Converted<'a, II, F> {
+    inner: &'a II,
+    convert: F,
+}
+
+impl<const K: usize, S: Scalar, II: Interval<K>, F: Fn(II::Scalar) -> S> Interval<K>
+    for Converted<'_, II, F>
+{
+    type Scalar = S;
+    fn min_at(&self, k: usize) -> S {
+        (self.convert)(self.inner.min_at(k))
+    }
+
+    fn max_at(&self, k: usize) -> S {
+        (self.convert)(self.inner.max_at(k))
+    }
+
+    fn dimension(&self) -> usize {
+        self.inner.dimension()
+    }
+}
+
+/// Query interval spanning [lo, hi] on the given axis, see scan_axis (and
+/// IntervalCore::dyn_overlaps_at). Its other dimensions are never read.
+pub(crate) struct AxisBounds<S> {
+    pub(crate) axis: usize,
+    pub(crate) lo: S,
+    pub(crate) hi: S,
+}
+
+impl<const K: usize, S: Scalar> Interval<K> for AxisBounds<S> {
+    type Scalar = S;
+    fn min_at(&self, _k: usize) -> S {
+        self.lo
+    }
+
+    fn max_at(&self, _k: usize) -> S {
+        self.hi
+    }
+
+    fn dimension(&self) -> usize {
+        self.axis + 1
+    }
+}
+
+/// Distance between a and b on the kth dimension, 0 if they overlap there.
+fn gap_at<const K: usize, I: Interval<K>, II: Interval<K, Scalar = I::Scalar>>(
+    a: &I,
+    b: &II,
+    k: usize,
+) -> I::Scalar {
+    // Comparing first avoids underflows with unsigned scalars
+    if a.min_at(k) > b.max_at(k) {
+        a.min_at(k) - b.max_at(k)
+    } else if b.min_at(k) > a.max_at(k) {
+        b.min_at(k) - a.max_at(k)
+    } else {
+        I::Scalar::zero()
+    }
+}
+
+/// Squared euclidean distance between the closest points of a and b, 0 if they overlap.
+pub(crate) fn distance_sq<const K: usize, I: Interval<K>, II: Interval<K, Scalar = I::Scalar>>(
+    a: &I,
+    b: &II,
+) -> I::Scalar {
+    (0..a.dimension())
+        .map(|k| gap_at(a, b, k))
+        .fold(I::Scalar::zero(), |acc, gap| acc + gap * gap)
+}
+
+/// Leaf of a tree, as a heap element: always equal, leaves being ordered by another key.
+struct LeafRef<'a, I>(&'a [I]);
+
+impl<I> PartialEq for LeafRef<'_, I> {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl<I> Eq for LeafRef<'_, I> {}
+
+impl<I> PartialOrd for LeafRef<'_, I> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<I> Ord for LeafRef<'_, I> {
+    fn cmp(&self, _other: &Self) -> Ordering {
+        Ordering::Equal
     }
 }
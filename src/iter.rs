@@ -1,4 +1,10 @@
-use crate::{Interval, IntervalTreeNode, NodeContent};
+use crate::interval_tree::distance_sq;
+use crate::{Interval, IntervalTreeNode, NodeContent, PathStep, Point};
+use num_traits::Zero;
+use std::cell::OnceCell;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::iter::FusedIterator;
 
 pub enum NodeContentIter<'a, I: Interval<K>, const K: usize> {
     Subtree(IntervalTreeIterator<'a, I, K>),
@@ -19,36 +25,95 @@ impl<'a, I: Interval<K>, const K: usize> Iterator for NodeContentIter<'a, I, K>
 impl<'a, I: Interval<K>, const K: usize> NodeContentIter<'a, I, K> {
     pub(crate) fn new(node_content: &'a NodeContent<I, K>) -> Self {
         match node_content {
-            NodeContent::Subtree(i) => NodeContentIter::Subtree(i.iter()),
+            NodeContent::Subtree(i) => NodeContentIter::Subtree(IntervalTreeIterator::new(i)),
             NodeContent::Leaf(v) => NodeContentIter::Leaf(v.iter()),
         }
     }
 }
 
+impl<I: Interval<K>, const K: usize> FusedIterator for NodeContentIter<'_, I, K> {}
+
+impl<'a, I: Interval<K>, const K: usize> DoubleEndedIterator for NodeContentIter<'a, I, K> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        match self {
+            NodeContentIter::Subtree(n) => n.next_back(),
+            NodeContentIter::Leaf(i) => i.next_back(),
+        }
+    }
+}
+
 impl<'a, I: Interval<K>, const K: usize> IntervalTreeIterator<'a, I, K> {
     pub(crate) fn new(curr_node: &'a IntervalTreeNode<I, K>) -> Self {
-        let it = if let Some(lt) = &curr_node.lt_nodes {
-            CurrentIterationState::Left(IntervalTreeIterator::new(lt))
-        } else {
-            CurrentIterationState::Center(NodeContentIter::new(&curr_node.center))
-        };
         IntervalTreeIterator {
             curr_node,
-            it: Box::new(it),
+            front: 0,
+            back: 3,
+            lt: None,
+            center: None,
+            gt: None,
+            total: OnceCell::new(),
+            yielded: 0,
+        }
+    }
+
+    /// Returns the next interval of the given part of the node (0: lt_nodes, 1: center,
+    /// 2: gt_nodes), from its front or its back.
+    /// The iterators of the parts are only created when reached, and shared by both ends, so an
+    /// interval is never yielded twice.
+    fn part_next(&mut self, part: usize, from_back: bool) -> Option<&'a I> {
+        fn next<'a, T: DoubleEndedIterator<Item = &'a I>, I: 'a>(
+            it: &mut T,
+            from_back: bool,
+        ) -> Option<&'a I> {
+            if from_back {
+                it.next_back()
+            } else {
+                it.next()
+            }
+        }
+
+        let node = self.curr_node;
+        match part {
+            0 => {
+                let lt = node.lt_nodes.as_deref()?;
+                next(
+                    self.lt
+                        .get_or_insert_with(|| Box::new(IntervalTreeIterator::new(lt))),
+                    from_back,
+                )
+            }
+            1 => next(
+                self.center
+                    .get_or_insert_with(|| Box::new(NodeContentIter::new(&node.center))),
+                from_back,
+            ),
+            _ => {
+                let gt = node.gt_nodes.as_deref()?;
+                next(
+                    self.gt
+                        .get_or_insert_with(|| Box::new(IntervalTreeIterator::new(gt))),
+                    from_back,
+                )
+            }
         }
     }
 }
 
+/// Iterator over all the intervals of a tree, as returned by IntervalTreeNode::iter.
+/// Iterates from both ends, and is fused: once exhausted, next and next_back keep returning None.
 pub struct IntervalTreeIterator<'a, I: Interval<K>, const K: usize> {
     curr_node: &'a IntervalTreeNode<I, K>,
-    it: Box<CurrentIterationState<'a, I, K>>,
-}
-
-enum CurrentIterationState<'a, I: Interval<K>, const K: usize> {
-    Left(IntervalTreeIterator<'a, I, K>),
-    Center(NodeContentIter<'a, I, K>),
-    Right(IntervalTreeIterator<'a, I, K>),
-    None,
+    /// The parts of the node in front..back have not been fully consumed yet.
+    front: usize,
+    back: usize,
+    lt: Option<Box<IntervalTreeIterator<'a, I, K>>>,
+    center: Option<Box<NodeContentIter<'a, I, K>>>,
+    gt: Option<Box<IntervalTreeIterator<'a, I, K>>>,
+    /// Number of intervals of the tree, only counted on the first call to size_hint (or len), so
+    /// that creating the iterator stays O(1).
+    total: OnceCell<usize>,
+    /// Number of intervals yielded so far, from both ends.
+    yielded: usize,
 }
 
 // This could trivially be implemented by returning a range_search with an infinitely sized
@@ -57,27 +122,211 @@ impl<'a, I: Interval<K>, const K: usize> Iterator for IntervalTreeIterator<'a, I
     type Item = &'a I;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if let CurrentIterationState::Left(it) = &mut *self.it {
-            if let lt @ Some(_) = it.next() {
-                return lt;
+        while self.front < self.back {
+            if let i @ Some(_) = self.part_next(self.front, false) {
+                self.yielded += 1;
+                return i;
+            }
+            self.front += 1;
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.total.get_or_init(|| self.curr_node.len()) - self.yielded;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<I: Interval<K>, const K: usize> ExactSizeIterator for IntervalTreeIterator<'_, I, K> {}
+
+// Once front and back met, they are never moved again
+impl<I: Interval<K>, const K: usize> FusedIterator for IntervalTreeIterator<'_, I, K> {}
+
+impl<'a, I: Interval<K>, const K: usize> DoubleEndedIterator for IntervalTreeIterator<'a, I, K> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        while self.front < self.back {
+            if let i @ Some(_) = self.part_next(self.back - 1, true) {
+                self.yielded += 1;
+                return i;
+            }
+            self.back -= 1;
+        }
+        None
+    }
+}
+
+enum DistanceItem<'a, I: Interval<K>, const K: usize> {
+    Node(&'a IntervalTreeNode<I, K>),
+    Interval(&'a I),
+}
+
+/// Element of the queue of DistanceIter: distance is the exact distance for intervals, and a lower
+/// bound of the distance of all their intervals for nodes.
+struct DistanceEntry<'a, I: Interval<K>, const K: usize> {
+    distance: I::Scalar,
+    item: DistanceItem<'a, I, K>,
+}
+
+impl<I: Interval<K>, const K: usize> PartialEq for DistanceEntry<'_, I, K> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<I: Interval<K>, const K: usize> Eq for DistanceEntry<'_, I, K> {}
+
+impl<I: Interval<K>, const K: usize> PartialOrd for DistanceEntry<'_, I, K> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Reversed, so that the BinaryHeap pops the closest entry first
+impl<I: Interval<K>, const K: usize> Ord for DistanceEntry<'_, I, K> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.distance.partial_cmp(&self.distance).unwrap()
+    }
+}
+
+/// Iterates over all intervals of a tree, by increasing distance to a point.
+/// The tree is traversed lazily (best-first), so taking only the first few intervals is cheap.
+pub struct DistanceIter<'a, 'p, I: Interval<K>, P, const K: usize> {
+    point: &'p P,
+    queue: BinaryHeap<DistanceEntry<'a, I, K>>,
+}
+
+impl<'a, 'p, I: Interval<K>, P: Point<K, Scalar = I::Scalar>, const K: usize>
+    DistanceIter<'a, 'p, I, P, K>
+{
+    pub(crate) fn new(root: &'a IntervalTreeNode<I, K>, point: &'p P) -> Self {
+        let mut queue = BinaryHeap::new();
+        queue.push(DistanceEntry {
+            distance: I::Scalar::zero(),
+            item: DistanceItem::Node(root),
+        });
+        DistanceIter { point, queue }
+    }
+
+    fn push_node(&mut self, node: &'a IntervalTreeNode<I, K>, bound: I::Scalar) {
+        match &node.center {
+            NodeContent::Subtree(n) => self.queue.push(DistanceEntry {
+                distance: bound,
+                item: DistanceItem::Node(n),
+            }),
+            NodeContent::Leaf(intervals) => {
+                for i in intervals {
+                    self.queue.push(DistanceEntry {
+                        distance: distance_sq(i, self.point),
+                        item: DistanceItem::Interval(i),
+                    });
+                }
             }
-            *self.it = CurrentIterationState::Center(NodeContentIter::new(&self.curr_node.center));
         }
 
-        if let CurrentIterationState::Center(it) = &mut *self.it {
-            if let lt @ Some(_) = it.next() {
-                return lt;
+        // All the intervals of lt_nodes (resp. gt_nodes) end before (resp. start after)
+        // center_val, so they are at least as far as it
+        let v = self.point.value(node.k);
+        let children = [
+            (&node.lt_nodes, v > node.center_val),
+            (&node.gt_nodes, v < node.center_val),
+        ];
+        for (child, further) in children {
+            if let Some(n) = child {
+                let gap = if further {
+                    let gap = if v > node.center_val {
+                        v - node.center_val
+                    } else {
+                        node.center_val - v
+                    };
+                    gap * gap
+                } else {
+                    I::Scalar::zero()
+                };
+                self.queue.push(DistanceEntry {
+                    distance: if gap > bound { gap } else { bound },
+                    item: DistanceItem::Node(n),
+                });
             }
-            *self.it = match &self.curr_node.gt_nodes {
-                None => CurrentIterationState::None,
-                Some(gt_nodes) => CurrentIterationState::Right(IntervalTreeIterator::new(gt_nodes)),
-            };
         }
+    }
+}
+
+impl<'a, I: Interval<K>, P: Point<K, Scalar = I::Scalar>, const K: usize> Iterator
+    for DistanceIter<'a, '_, I, P, K>
+{
+    type Item = &'a I;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(DistanceEntry { distance, item }) = self.queue.pop() {
+            match item {
+                DistanceItem::Interval(i) => return Some(i),
+                DistanceItem::Node(n) => self.push_node(n, distance),
+            }
+        }
+        None
+    }
+}
+
+/// Iterates over the intervals of a tree overlapping with a query, in the same order as
+/// range_search.
+/// The nodes left to visit are kept on a stack, on which the subtrees that cannot contain any
+/// overlapping interval are never pushed: nothing is allocated for the results, and stopping
+/// early skips the rest of the traversal.
+pub struct Intersecting<'a, I: Interval<K>, II, const K: usize> {
+    query: &'a II,
+    stack: Vec<&'a IntervalTreeNode<I, K>>,
+    leaf: std::slice::Iter<'a, I>,
+}
+
+impl<'a, I: Interval<K>, II: Interval<K, Scalar = I::Scalar>, const K: usize>
+    Intersecting<'a, I, II, K>
+{
+    pub(crate) fn new(root: &'a IntervalTreeNode<I, K>, query: &'a II) -> Self {
+        Intersecting {
+            query,
+            stack: vec![root],
+            leaf: [].iter(),
+        }
+    }
 
-        match &mut *self.it {
-            CurrentIterationState::Right(it) => it.next(),
-            CurrentIterationState::None => None,
-            _ => unreachable!(),
+    /// Same pruning as IntervalTreeNode::visit.
+    fn visit(&mut self, node: &'a IntervalTreeNode<I, K>) {
+        if !node.reaches_bbox(self.query) {
+            return;
+        }
+        // Pushed in reverse order, so that the center is visited first, then lt_nodes and gt_nodes
+        if node.reaches(self.query, PathStep::Gt) {
+            self.stack.extend(node.gt_nodes.as_deref());
+        }
+        if node.reaches(self.query, PathStep::Lt) {
+            self.stack.extend(node.lt_nodes.as_deref());
+        }
+        match &node.center {
+            NodeContent::Subtree(n) => self.stack.push(n),
+            NodeContent::Leaf(intervals) => self.leaf = intervals.iter(),
         }
     }
 }
+
+impl<'a, I: Interval<K>, II: Interval<K, Scalar = I::Scalar>, const K: usize> Iterator
+    for Intersecting<'a, I, II, K>
+{
+    type Item = &'a I;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let query = self.query;
+            if let Some(i) = self.leaf.find(|i| i.overlaps(query)) {
+                return Some(i);
+            }
+            let node = self.stack.pop()?;
+            self.visit(node);
+        }
+    }
+}
+
+impl<I: Interval<K>, II: Interval<K, Scalar = I::Scalar>, const K: usize> FusedIterator
+    for Intersecting<'_, I, II, K>
+{
+}
@@ -1,5 +1,34 @@
+mod box_interval;
+mod builder;
+mod chained;
+mod cursor;
+mod dyn_interval;
+#[cfg(feature = "geo")]
+mod geo;
+mod grid;
+mod index;
+mod indexed;
+mod interval_ref;
 mod interval_tree;
 pub mod iter;
+mod managed;
+mod periodic;
+#[cfg(feature = "io")]
+mod reader;
+mod temporal;
 #[cfg(test)]
 mod tests;
+mod visit;
+pub use crate::box_interval::*;
+pub use crate::builder::*;
+pub use crate::chained::*;
+pub use crate::cursor::*;
+pub use crate::dyn_interval::*;
+pub use crate::grid::*;
+pub use crate::index::*;
+pub use crate::indexed::*;
+pub use crate::interval_ref::*;
 pub use crate::interval_tree::*;
+pub use crate::managed::*;
+pub use crate::periodic::*;
+pub use crate::temporal::*;
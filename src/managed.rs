@@ -0,0 +1,196 @@
+use crate::{Interval, IntervalTreeNode};
+
+/// Wraps a tree updated through insert and remove, rebuilding it from scratch whenever its height
+/// drifts too far from the ideal log2(len()), so that the query time stays predictable under
+/// churn.
+/// Alternatively (see deferred), the mutations can be buffered until the next flush, which
+/// rebuilds the tree once for a whole burst of them.
+pub struct ManagedIntervalTree<I: Interval<K>, const K: usize> {
+    /// None until the first interval is inserted (trees cannot be built from no interval).
+    tree: Option<IntervalTreeNode<I, K>>,
+    len: usize,
+    /// Upper bound of the height of the tree, tracked through the insertions.
+    height: usize,
+    /// Height of the tree right after it was last rebuilt.
+    rebuilt_height: usize,
+    mutations: usize,
+    rebalances: usize,
+    max_height_ratio: f64,
+    /// Whether the mutations are buffered until the next flush.
+    deferred: bool,
+    /// Whether the tree was mutated since it was last rebuilt, in deferred mode.
+    dirty: bool,
+    /// The intervals inserted since the tree was last rebuilt, in deferred mode.
+    pending: Vec<I>,
+}
+
+impl<I: Interval<K>, const K: usize> ManagedIntervalTree<I, K> {
+    /// Creates a ManagedIntervalTree given a collection of intervals, rebalancing the tree when
+    /// its height exceeds twice the ideal one.
+    pub fn new(intervals: Vec<I>) -> ManagedIntervalTree<I, K> {
+        ManagedIntervalTree::with_max_height_ratio(intervals, 2.0)
+    }
+
+    /// Same as new, rebalancing the tree when its height exceeds max_height_ratio times the ideal
+    /// one.
+    pub fn with_max_height_ratio(
+        intervals: Vec<I>,
+        max_height_ratio: f64,
+    ) -> ManagedIntervalTree<I, K> {
+        assert!(
+            max_height_ratio >= 1.0,
+            "The height of a tree cannot be lower than the ideal one!"
+        );
+        let mut managed = ManagedIntervalTree {
+            tree: None,
+            len: 0,
+            height: 0,
+            rebuilt_height: 0,
+            mutations: 0,
+            rebalances: 0,
+            max_height_ratio,
+            deferred: false,
+            dirty: false,
+            pending: Vec::new(),
+        };
+        managed.rebuild(intervals);
+        managed
+    }
+
+    /// Same as new, buffering the insertions (and marking the tree dirty on removals) until the
+    /// next call to flush or range_search, which then rebuild the tree once. This
+    /// amortizes the cost of bursts of mutations followed by many queries.
+    pub fn deferred(intervals: Vec<I>) -> ManagedIntervalTree<I, K> {
+        let mut managed = ManagedIntervalTree::new(intervals);
+        managed.deferred = true;
+        managed
+    }
+
+    /// Inserts an interval, rebalancing the tree if needed (or buffering it, in deferred mode).
+    pub fn insert(&mut self, interval: I) {
+        if self.deferred {
+            self.pending.push(interval);
+            self.dirty = true;
+            self.len += 1;
+            self.mutations += 1;
+            return;
+        }
+        match &mut self.tree {
+            Some(tree) => {
+                self.height = self.height.max(tree.insert_rec(interval, 1));
+                self.len += 1;
+            }
+            // rebuild already accounts for the interval in len
+            None => self.rebuild(vec![interval]),
+        }
+        self.mutations += 1;
+        self.rebalance_if_needed();
+    }
+
+    /// Removes an interval equal to the given one and returns it (None if there is no such
+    /// interval), rebalancing the tree if needed (or marking it dirty, in deferred mode).
+    pub fn remove(&mut self, interval: &I) -> Option<I>
+    where
+        I: PartialEq,
+    {
+        let removed = match self.pending.iter().position(|i| i == interval) {
+            Some(position) => self.pending.swap_remove(position),
+            None => self.tree.as_mut()?.remove(interval)?,
+        };
+        self.len -= 1;
+        self.mutations += 1;
+        if self.deferred {
+            self.dirty = true;
+        } else {
+            self.rebalance_if_needed();
+        }
+        Some(removed)
+    }
+
+    /// Rebuilds the tree with the pending insertions, if it was mutated since it was last
+    /// rebuilt. Only needed in deferred mode.
+    pub fn flush(&mut self) {
+        if self.dirty {
+            self.rebalance();
+        }
+    }
+
+    /// Rebuilds the tree from scratch (with the pending insertions, in deferred mode).
+    pub fn rebalance(&mut self) {
+        let mut intervals = self
+            .tree
+            .take()
+            .map_or_else(Vec::new, IntervalTreeNode::into_intervals);
+        intervals.append(&mut self.pending);
+        self.rebuild(intervals);
+        self.rebalances += 1;
+    }
+
+    fn rebuild(&mut self, intervals: Vec<I>) {
+        self.len = intervals.len();
+        self.tree = (!intervals.is_empty()).then(|| IntervalTreeNode::from_intervals(intervals));
+        self.height = self.tree.as_ref().map_or(0, IntervalTreeNode::height);
+        self.rebuilt_height = self.height;
+        self.mutations = 0;
+        self.dirty = false;
+    }
+
+    /// log2(len()), rounded up (and at least 1).
+    fn ideal_height(&self) -> usize {
+        (usize::BITS - self.len.saturating_sub(1).leading_zeros()).max(1) as usize
+    }
+
+    fn rebalance_if_needed(&mut self) {
+        // A tree which was too high right after being built would otherwise be rebuilt on every
+        // mutation
+        if self.height > self.rebuilt_height
+            && self.height as f64 > self.max_height_ratio * self.ideal_height() as f64
+        {
+            self.rebalance();
+        }
+    }
+
+    /// Same as IntervalTreeNode::range_search. In deferred mode, the pending mutations are
+    /// flushed first, i.e. the first query after a burst of them rebuilds the tree.
+    pub fn range_search<II: Interval<K, Scalar = I::Scalar>>(&mut self, x: &II) -> Vec<&I> {
+        self.flush();
+        self.tree
+            .as_ref()
+            .map_or_else(Vec::new, |tree| tree.range_search(x))
+    }
+
+    /// The managed tree, if any interval was ever inserted. In deferred mode, it does not contain
+    /// the pending insertions.
+    pub fn tree(&self) -> Option<&IntervalTreeNode<I, K>> {
+        self.tree.as_ref()
+    }
+
+    /// Returns the number of nodes on the longest path from the root to a leaf.
+    pub fn height(&self) -> usize {
+        self.tree.as_ref().map_or(0, IntervalTreeNode::height)
+    }
+
+    /// The number of intervals in the tree.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Whether the tree was mutated since it was last rebuilt, in deferred mode.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// The number of insertions and removals since the tree was last rebuilt.
+    pub fn mutations(&self) -> usize {
+        self.mutations
+    }
+
+    /// The number of times the tree was rebuilt.
+    pub fn rebalances(&self) -> usize {
+        self.rebalances
+    }
+}
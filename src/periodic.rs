@@ -0,0 +1,97 @@
+use crate::{Interval, Point};
+use num_traits::One;
+use std::cmp::Ordering;
+
+/// Wraps an interval whose axis-th dimension is periodic (e.g. longitudes), on which min_at > max_at
+/// means the interval wraps around: 170..-170 is the union of 170..180 and -180..-170.
+/// The other dimensions are left untouched.
+/// Wrapping intervals always end up in the center of the nodes splitting on axis, and queries
+/// wrapping around have to be Periodic as well (so that both sides of such nodes are searched).
+/// Note that the volume computations do not account for the wraparound.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Periodic<I> {
+    interval: I,
+    axis: usize,
+}
+
+impl<I> Periodic<I> {
+    pub fn new(interval: I, axis: usize) -> Periodic<I> {
+        Periodic { interval, axis }
+    }
+
+    /// The periodic dimension.
+    pub fn axis(&self) -> usize {
+        self.axis
+    }
+
+    pub fn interval(&self) -> &I {
+        &self.interval
+    }
+
+    pub fn into_inner(self) -> I {
+        self.interval
+    }
+}
+
+impl<const K: usize, I: Interval<K>> Interval<K> for Periodic<I> {
+    type Scalar = I::Scalar;
+    fn min_at(&self, k: usize) -> Self::Scalar {
+        self.interval.min_at(k)
+    }
+
+    fn max_at(&self, k: usize) -> Self::Scalar {
+        self.interval.max_at(k)
+    }
+
+    /// A wrapping interval covers both ends of its periodic dimension, averaged as the middle of
+    /// the gap between max_at and min_at instead (so that unsigned scalars do not underflow).
+    fn avg_at(&self, k: usize) -> Self::Scalar {
+        let (min, max) = (self.min_at(k), self.max_at(k));
+        if k == self.axis && min > max {
+            max + (min - max) / (Self::Scalar::one() + Self::Scalar::one())
+        } else {
+            self.interval.avg_at(k)
+        }
+    }
+
+    /// o is considered to wrap around as well when its min_at is greater than its max_at.
+    fn overlaps_at<II: Interval<K, Scalar = Self::Scalar>>(&self, k: usize, o: &II) -> bool {
+        if k != self.axis {
+            return self.interval.overlaps_at(k, o);
+        }
+        let (min, max) = (self.min_at(k), self.max_at(k));
+        let (o_min, o_max) = (o.min_at(k), o.max_at(k));
+        match (min > max, o_min > o_max) {
+            (false, false) => min <= o_max && o_min <= max,
+            (true, false) => o_max >= min || o_min <= max,
+            (false, true) => max >= o_min || min <= o_max,
+            // Both contain the wrapping point
+            (true, true) => true,
+        }
+    }
+
+    fn contains_point<P: Point<K, Scalar = Self::Scalar>>(&self, p: &P) -> bool {
+        (0..self.dimension()).all(|k| {
+            let (min, max, v) = (self.min_at(k), self.max_at(k), p.value(k));
+            if k == self.axis && min > max {
+                v >= min || v <= max
+            } else {
+                min <= v && v <= max
+            }
+        })
+    }
+
+    fn dimension(&self) -> usize {
+        self.interval.dimension()
+    }
+
+    /// A wrapping interval extends to both ends of its periodic dimension, so it is never
+    /// entirely on one side of s.
+    fn cmp_at(&self, k: usize, s: Self::Scalar) -> Ordering {
+        if k == self.axis && self.min_at(k) > self.max_at(k) {
+            Ordering::Equal
+        } else {
+            self.interval.cmp_at(k, s)
+        }
+    }
+}
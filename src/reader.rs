@@ -0,0 +1,50 @@
+use crate::{Interval, IntervalTreeNode};
+use std::io::{ErrorKind, Read};
+
+impl<const K: usize, I: Interval<K>> IntervalTreeNode<I, K> {
+    /// Creates an IntervalTreeNode from fixed-size binary records, each of them being turned into
+    /// an interval by parse.
+    /// All the intervals are still loaded in memory before building the tree (the medians need
+    /// to be known), this only saves the boilerplate of reading and parsing the records.
+    /// Fails with InvalidData if the stream ends in the middle of a record, or does not contain
+    /// any record.
+    pub fn from_reader<R: Read, F: Fn(&[u8]) -> I>(
+        mut reader: R,
+        record_size: usize,
+        parse: F,
+    ) -> std::io::Result<IntervalTreeNode<I, K>> {
+        assert!(record_size > 0, "Records should not be empty!");
+        let mut intervals = Vec::new();
+        let mut record = vec![0; record_size];
+        loop {
+            let mut filled = 0;
+            while filled < record_size {
+                match reader.read(&mut record[filled..]) {
+                    Ok(0) => break,
+                    Ok(n) => filled += n,
+                    Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e),
+                }
+            }
+
+            if filled == record_size {
+                intervals.push(parse(&record));
+            } else if filled == 0 {
+                break;
+            } else {
+                return Err(std::io::Error::new(
+                    ErrorKind::InvalidData,
+                    "the input ends with a truncated record",
+                ));
+            }
+        }
+
+        if intervals.is_empty() {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidData,
+                "the input does not contain any record",
+            ));
+        }
+        Ok(IntervalTreeNode::from_intervals(intervals))
+    }
+}
@@ -0,0 +1,73 @@
+use crate::{BoxInterval, BuildError, Interval, IntervalTreeNode, Scalar};
+
+/// A value active from start to end (both included), as stored in a TemporalTree.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimeInterval<T, V> {
+    pub start: T,
+    pub end: T,
+    pub value: V,
+}
+
+impl<T: Scalar, V> Interval<1> for TimeInterval<T, V> {
+    type Scalar = T;
+    fn min_at(&self, _k: usize) -> T {
+        self.start
+    }
+
+    fn max_at(&self, _k: usize) -> T {
+        self.end
+    }
+}
+
+/// Thin wrapper around a 1-dimensional tree, for values active over time intervals.
+pub struct TemporalTree<V, T: Scalar = f64> {
+    tree: IntervalTreeNode<TimeInterval<T, V>, 1>,
+}
+
+impl<V, T: Scalar> TemporalTree<V, T> {
+    /// Creates a TemporalTree given a collection of (start, end, value).
+    ///
+    /// # Panics
+    ///
+    /// Panics if intervals is empty, as IntervalTreeNode::from_intervals does (see try_new for a
+    /// non-panicking construction).
+    pub fn new(intervals: Vec<(T, T, V)>) -> TemporalTree<V, T> {
+        TemporalTree {
+            tree: IntervalTreeNode::from_intervals(time_intervals(intervals)),
+        }
+    }
+
+    /// Same as new, returning an error instead of panicking when intervals is empty (or when some
+    /// bounds cannot be compared, see IntervalTreeNode::try_from_intervals).
+    pub fn try_new(intervals: Vec<(T, T, V)>) -> Result<TemporalTree<V, T>, BuildError> {
+        Ok(TemporalTree {
+            tree: IntervalTreeNode::try_from_intervals(time_intervals(intervals))?,
+        })
+    }
+
+    /// Returns the values active at t.
+    pub fn active_at(&self, t: T) -> Vec<&V> {
+        self.active_during(t, t)
+    }
+
+    /// Returns the values active at some point between t0 and t1 (both included).
+    pub fn active_during(&self, t0: T, t1: T) -> Vec<&V> {
+        self.tree
+            .range_search(&BoxInterval([(t0, t1)]))
+            .into_iter()
+            .map(|i| &i.value)
+            .collect()
+    }
+
+    /// The underlying tree, for the queries not exposed by TemporalTree.
+    pub fn tree(&self) -> &IntervalTreeNode<TimeInterval<T, V>, 1> {
+        &self.tree
+    }
+}
+
+fn time_intervals<T, V>(intervals: Vec<(T, T, V)>) -> Vec<TimeInterval<T, V>> {
+    intervals
+        .into_iter()
+        .map(|(start, end, value)| TimeInterval { start, end, value })
+        .collect()
+}
@@ -1,7 +1,18 @@
-use crate::{Interval, IntervalTreeNode, NodeContent, Point};
-use std::borrow::Borrow;
+use crate::interval_tree::distance_sq;
+use crate::{
+    BoxInterval, BuildError, BuildReport, Interval, IntervalCore, IntervalGrid, IntervalIndex,
+    IntervalTreeBuilder, IntervalTreeNode, InvariantError, ManagedIntervalTree, MedianBias,
+    NodeContent, PathStep, Periodic, Point, ProjectedInterval, SortKey, TemporalTree, TreeStats,
+};
+use rand::distributions::Uniform;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::borrow::{Borrow, Cow};
+use std::cmp::Ordering;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Rectangle {
     xmin: f64,
     xmax: f64,
@@ -29,6 +40,14 @@ impl Interval<2> for Rectangle {
     }
 }
 
+impl Hash for Rectangle {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for v in [self.xmin, self.xmax, self.ymin, self.ymax] {
+            v.to_bits().hash(state);
+        }
+    }
+}
+
 impl Rectangle {
     fn new(xmin: f64, xmax: f64, ymin: f64, ymax: f64) -> Self {
         Rectangle {
@@ -40,17 +59,6 @@ impl Rectangle {
     }
 }
 
-impl Point<2> for (f64, f64) {
-    type Scalar = f64;
-    fn value(&self, k: usize) -> f64 {
-        match k {
-            0 => self.0,
-            1 => self.1,
-            _ => unreachable!(),
-        }
-    }
-}
-
 fn basic_tree_rectangles() -> Vec<Rectangle> {
     vec![
         Rectangle::new(2.0, 3.0, 5.0, 6.0),
@@ -80,6 +88,11 @@ fn assert_approx<R1: Borrow<Rectangle>, R2: Borrow<Rectangle>>(a: R1, b: R2) {
     );
 }
 
+fn sorted_by_x(mut intervals: Vec<&Rectangle>) -> Vec<&Rectangle> {
+    intervals.sort_by(|a, b| a.avg_at(0).partial_cmp(&b.avg_at(0)).unwrap());
+    intervals
+}
+
 #[test]
 fn test_tree_creation() {
     let tree = basic_tree();
@@ -88,13 +101,8 @@ fn test_tree_creation() {
         let lt_nodes = tree.lt_nodes.unwrap();
         assert!(lt_nodes.lt_nodes.is_none());
         assert!(lt_nodes.gt_nodes.is_none());
-        let lt_nodes_center = match lt_nodes.center {
-            NodeContent::Subtree(n) => n,
-            _ => unreachable!(),
-        };
-        assert!(lt_nodes_center.lt_nodes.is_none());
-        assert!(lt_nodes_center.gt_nodes.is_none());
-        let lt_nodes_intervals = match lt_nodes_center.center {
+        // A single interval cannot be split further: it is directly stored in a leaf
+        let lt_nodes_intervals = match lt_nodes.center {
             NodeContent::Leaf(intervals) => intervals,
             _ => unreachable!(),
         };
@@ -106,13 +114,8 @@ fn test_tree_creation() {
         let gt_nodes = tree.gt_nodes.unwrap();
         assert!(gt_nodes.lt_nodes.is_none());
         assert!(gt_nodes.gt_nodes.is_none());
-        let gt_nodes_center = match gt_nodes.center {
-            NodeContent::Subtree(n) => n,
-            _ => unreachable!(),
-        };
-        assert!(gt_nodes_center.lt_nodes.is_none());
-        assert!(gt_nodes_center.gt_nodes.is_none());
-        let gt_nodes_intervals = match gt_nodes_center.center {
+        // A single interval cannot be split further: it is directly stored in a leaf
+        let gt_nodes_intervals = match gt_nodes.center {
             NodeContent::Leaf(intervals) => intervals,
             _ => unreachable!(),
         };
@@ -184,6 +187,21 @@ fn test_tree_iter() {
     }
 }
 
+#[test]
+fn test_interval_volume() {
+    let rect = Rectangle::new(0.0, 4.0, -3.0, 2.0);
+    assert_eq!(rect.volume(), 20.0);
+    assert_eq!(rect.surface_measure(), 18.0);
+    assert_eq!((3.0, 1.5).volume(), 0.0);
+    assert_eq!(BoxInterval([(0, 2), (0, 3), (0, 4)]).volume(), 24);
+    assert_eq!(BoxInterval([(0, 2), (0, 3), (0, 4)]).surface_measure(), 52);
+
+    // Intersection over union
+    let other = Rectangle::new(2.0, 6.0, -3.0, 2.0);
+    let inter = rect.overlapping_volume(&other);
+    assert_eq!(inter / (rect.volume() + other.volume() - inter), 1.0 / 3.0);
+}
+
 #[test]
 fn test_tree_measure_volume() {
     let rect = Rectangle::new(1.0, 4.0, 2.5, 6.0);
@@ -205,3 +223,2429 @@ fn test_tree_measure_volume() {
     assert_eq!(areas_from_try[2], 0.5);
     assert_eq!(areas_from_try, areas_assuming_overlap);
 }
+
+#[test]
+fn test_tree_from_presorted() {
+    let mut intervals = basic_tree_rectangles();
+    intervals.sort_by(|a, b| a.avg_at(0).partial_cmp(&b.avg_at(0)).unwrap());
+    let presorted = IntervalTreeNode::<_, 2>::from_presorted(intervals);
+    let tree = basic_tree();
+
+    assert_eq!(presorted.iter().count(), tree.iter().count());
+    for (a, b) in presorted.iter().zip(tree.iter()) {
+        assert_approx(a, b);
+    }
+
+    let rect = Rectangle::new(1.0, 4.0, 2.5, 6.0);
+    let from_presorted = presorted.range_search(&rect);
+    let from_default = tree.range_search(&rect);
+    assert_eq!(from_presorted.len(), from_default.len());
+    for (a, b) in from_presorted.iter().zip(from_default.iter()) {
+        assert_approx(*a, *b);
+    }
+}
+
+#[test]
+fn test_tree_overlap_degrees() {
+    let tree = basic_tree();
+    let intervals = tree.iter().collect::<Vec<_>>();
+    let degrees = tree.overlap_degrees();
+    assert_eq!(degrees.len(), intervals.len());
+    for (i, degree) in degrees {
+        let brute_force = intervals
+            .iter()
+            .filter(|o| !std::ptr::eq(**o, i) && i.overlaps(**o))
+            .count();
+        assert_eq!(degree, brute_force, "Wrong degree for {i:?}");
+    }
+}
+
+#[test]
+fn test_tree_range_search_try() {
+    let rect = Rectangle::new(1.0, 4.0, 2.5, 6.0);
+    let tree = basic_tree();
+
+    let mut visited = Vec::new();
+    let res: Result<(), ()> = tree.range_search_try(&rect, |i| {
+        visited.push(i);
+        Ok(())
+    });
+    assert!(res.is_ok());
+    let expected = tree.range_search(&rect);
+    assert_eq!(visited.len(), expected.len());
+    for (a, b) in visited.iter().zip(expected.iter()) {
+        assert_approx(*a, *b);
+    }
+
+    let mut calls = 0;
+    let res = tree.range_search_try(&rect, |i| {
+        calls += 1;
+        Err(i)
+    });
+    assert_eq!(calls, 1);
+    assert_approx(res.unwrap_err(), expected[0]);
+}
+
+#[test]
+fn test_tree_merge() {
+    let other_rectangles = vec![
+        Rectangle::new(10.0, 12.0, 10.0, 12.0),
+        Rectangle::new(-1.0, 1.0, 0.0, 8.0),
+    ];
+    let other = IntervalTreeNode::from_intervals(other_rectangles.clone());
+    let merged = basic_tree().merge(other);
+
+    let mut expected = basic_tree_rectangles();
+    expected.extend(other_rectangles);
+    expected.sort_by(|a, b| a.avg_at(0).partial_cmp(&b.avg_at(0)).unwrap());
+    let mut collected = merged.iter().collect::<Vec<_>>();
+    collected.sort_by(|a, b| a.avg_at(0).partial_cmp(&b.avg_at(0)).unwrap());
+
+    assert_eq!(collected.len(), expected.len());
+    for (a, b) in collected.iter().zip(expected.iter()) {
+        assert_approx(*a, b);
+    }
+
+    // Emptied trees
+    let mut empty = basic_tree();
+    empty.retain(|_| false);
+    let mut other_empty = basic_tree();
+    other_empty.retain(|_| false);
+    assert!(empty.clone().merge(other_empty.clone()).is_empty());
+    assert_eq!(empty.clone().merge(basic_tree()).len(), 5);
+    assert_eq!(basic_tree().merge(other_empty).len(), 5);
+}
+
+#[test]
+fn test_tree_to_structure() {
+    let info = basic_tree().to_structure();
+    assert_eq!(info.k, 0);
+    // Averages on x are -2.0, -0.5, 2.0, 2.5 and 5.0
+    assert_eq!(info.center_val, 2.0);
+    assert_eq!(info.center_len, 3);
+
+    let center = info.center.unwrap();
+    assert_eq!(center.k, 1);
+    assert_eq!(center.center_len, 2);
+    assert!(center.center.is_none());
+    assert!(center.lt_nodes.is_none());
+    assert_eq!(center.gt_nodes.unwrap().center_len, 1);
+
+    let lt_nodes = info.lt_nodes.unwrap();
+    assert_eq!(lt_nodes.k, 0);
+    assert_eq!(lt_nodes.center_len, 1);
+    assert!(lt_nodes.lt_nodes.is_none() && lt_nodes.gt_nodes.is_none());
+    assert_eq!(info.gt_nodes.unwrap().center_len, 1);
+}
+
+/// Dynamically sized interval, i.e. K = 0 with dimension() overriden.
+#[derive(Clone, Debug)]
+struct DynInterval(Vec<(f64, f64)>);
+
+impl Interval<0> for DynInterval {
+    type Scalar = f64;
+    fn min_at(&self, k: usize) -> f64 {
+        self.0[k].0
+    }
+
+    fn max_at(&self, k: usize) -> f64 {
+        self.0[k].1
+    }
+
+    fn dimension(&self) -> usize {
+        self.0.len()
+    }
+}
+
+struct DynPoint(Vec<f64>);
+
+impl Point<0> for DynPoint {
+    type Scalar = f64;
+    fn value(&self, k: usize) -> f64 {
+        self.0[k]
+    }
+
+    fn dimension(&self) -> usize {
+        self.0.len()
+    }
+}
+
+fn dyn_tree_intervals() -> Vec<DynInterval> {
+    vec![
+        DynInterval(vec![(0.0, 1.0), (0.0, 1.0), (0.0, 1.0)]),
+        DynInterval(vec![(0.5, 2.0), (0.5, 2.0), (-1.0, 0.5)]),
+        DynInterval(vec![(-3.0, -1.0), (0.0, 4.0), (0.0, 4.0)]),
+        DynInterval(vec![(2.0, 3.0), (2.0, 3.0), (2.0, 3.0)]),
+        DynInterval(vec![(-1.0, 3.0), (-1.0, 0.2), (0.8, 3.0)]),
+    ]
+}
+
+#[test]
+fn test_dyn_tree_querying() {
+    let intervals = dyn_tree_intervals();
+    let tree = IntervalTreeNode::from_intervals(intervals.clone());
+    assert_eq!(tree.iter().count(), intervals.len());
+
+    let queries = [
+        DynInterval(vec![(0.6, 0.7), (0.6, 0.7), (0.2, 0.4)]),
+        DynInterval(vec![(-2.0, 0.0), (0.0, 0.1), (0.9, 1.0)]),
+        DynInterval(vec![(10.0, 11.0), (0.0, 1.0), (0.0, 1.0)]),
+    ];
+    for q in &queries {
+        let mut found = tree
+            .range_search(q)
+            .into_iter()
+            .map(|i| i.0.clone())
+            .collect::<Vec<_>>();
+        let mut expected = intervals
+            .iter()
+            .filter(|i| i.overlaps(q))
+            .map(|i| i.0.clone())
+            .collect::<Vec<_>>();
+        found.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(found, expected);
+    }
+    assert_eq!(tree.range_search(&queries[0]).len(), 2);
+    assert_eq!(tree.range_search(&queries[2]).len(), 0);
+
+    let point = DynPoint(vec![2.5, 2.5, 2.5]);
+    let found = tree.range_search(&point);
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].0, vec![(2.0, 3.0), (2.0, 3.0), (2.0, 3.0)]);
+}
+
+#[test]
+fn test_tree_stats() {
+    let stats = basic_tree().stats();
+    assert_eq!(
+        stats,
+        TreeStats {
+            nodes: 5,
+            leaves: 4,
+            intervals: 5,
+            height: 3,
+            leaf_capacity: stats.leaf_capacity,
+        }
+    );
+    assert!(stats.leaf_capacity >= 5);
+}
+
+#[test]
+fn test_tree_compact() {
+    let mut tree = basic_tree();
+    let before = tree.stats();
+    tree.retain(|r| r.xmin >= 2.0);
+    let kept = tree.iter().cloned().collect::<Vec<_>>();
+    assert_eq!(kept.len(), 2);
+    assert_eq!(tree.stats().nodes, before.nodes);
+
+    tree.compact();
+    let after = tree.stats();
+    assert!(after.nodes < before.nodes);
+    assert!(after.leaf_capacity < before.leaf_capacity);
+    assert_eq!(after.intervals, 2);
+    #[cfg(not(feature = "smallvec"))]
+    assert_eq!(after.leaf_capacity, 2);
+    // Small leaves always have room for their inline intervals
+    #[cfg(feature = "smallvec")]
+    assert_eq!(after.leaf_capacity, 2 * after.leaves);
+
+    let compacted = tree.iter().collect::<Vec<_>>();
+    assert_eq!(compacted.len(), kept.len());
+    for (a, b) in compacted.iter().zip(kept.iter()) {
+        assert_approx(*a, b);
+    }
+    let rect = Rectangle::new(1.0, 4.0, 2.5, 6.0);
+    assert_eq!(tree.range_search(&rect).len(), 2);
+}
+
+#[test]
+fn test_tree_range_search_expanded() {
+    let point = (5.0, 4.0);
+    let tree = basic_tree();
+    assert!(tree.range_search_expanded(&point, 0.0).is_empty());
+
+    let intervals = tree.range_search_expanded(&point, 1.0);
+    assert_eq!(intervals.len(), 1);
+    assert_approx(intervals[0], Rectangle::new(3.0, 7.0, 1.0, 3.0));
+}
+
+#[test]
+fn test_tree_eq() {
+    let mut reversed = basic_tree_rectangles();
+    reversed.reverse();
+    assert!(basic_tree() == IntervalTreeNode::from_intervals(reversed));
+
+    let mut missing = basic_tree_rectangles();
+    missing.pop();
+    assert!(basic_tree() != IntervalTreeNode::from_intervals(missing.clone()));
+
+    missing.push(missing[0].clone());
+    assert!(basic_tree() != IntervalTreeNode::from_intervals(missing));
+}
+
+#[test]
+fn test_tree_all_queries_hit() {
+    let tree = basic_tree();
+    let hitting = [
+        Rectangle::new(1.0, 4.0, 2.5, 6.0),
+        Rectangle::new(6.0, 8.0, 0.0, 1.0),
+    ];
+    let missing = Rectangle::new(10.0, 11.0, 10.0, 11.0);
+    assert!(tree.any_overlap(&hitting[0]));
+    assert!(!tree.any_overlap(&missing));
+
+    assert!(tree.all_queries_hit(&hitting));
+    assert!(tree.all_queries_hit::<Rectangle>(&[]));
+    let mut mixed = hitting.to_vec();
+    mixed.insert(1, missing);
+    assert!(!tree.all_queries_hit(&mixed));
+}
+
+#[test]
+fn test_tree_range_search_axes() {
+    let tree = basic_tree();
+    let rect = Rectangle::new(2.0, 4.0, 100.0, 101.0);
+    let on_x = sorted_by_x(tree.range_search_axes(&rect, &[0]));
+    let full_y = sorted_by_x(tree.range_search(&Rectangle::new(2.0, 4.0, -100.0, 100.0)));
+    assert_eq!(on_x.len(), 4);
+    assert_eq!(on_x, full_y);
+    assert!(tree.range_search(&rect).is_empty());
+    assert_eq!(tree.range_search_axes(&rect, &[]).len(), 5);
+}
+
+fn hash_of<T: Hash>(t: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    t.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[test]
+fn test_tree_hash() {
+    let tree = basic_tree();
+    assert_eq!(hash_of(&tree), hash_of(&tree.clone()));
+
+    let mut reversed = basic_tree_rectangles();
+    reversed.reverse();
+    assert_eq!(
+        hash_of(&tree),
+        hash_of(&IntervalTreeNode::from_intervals(reversed))
+    );
+
+    let mut missing = basic_tree_rectangles();
+    missing.pop();
+    assert_ne!(
+        hash_of(&tree),
+        hash_of(&IntervalTreeNode::from_intervals(missing))
+    );
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Segment(i32, i32);
+
+impl Interval<1> for Segment {
+    type Scalar = i32;
+    fn min_at(&self, _: usize) -> i32 {
+        self.0
+    }
+
+    fn max_at(&self, _: usize) -> i32 {
+        self.1
+    }
+}
+
+#[test]
+fn test_interval_index() {
+    let segments = vec![
+        Segment(0, 3),
+        Segment(2, 2),
+        Segment(5, 10),
+        Segment(-4, -1),
+        Segment(1, 6),
+        Segment(8, 9),
+    ];
+    let index = IntervalIndex::new(&segments);
+    assert_eq!(index.len(), segments.len());
+    for lo in -6..12 {
+        for hi in lo..12 {
+            let query = Segment(lo, hi);
+            let brute_force = segments.iter().filter(|s| s.overlaps(&query)).count();
+            assert_eq!(index.count_in_range(0, lo, hi), brute_force, "{lo} {hi}");
+        }
+    }
+
+    let tree = IntervalTreeNode::from_intervals(segments);
+    let from_tree = IntervalIndex::new(tree.iter());
+    assert_eq!(from_tree.count_in_range(0, 2, 5), 4);
+}
+
+#[test]
+fn test_tree_from_intervals_by() {
+    let reversed = |a: f64, b: f64| b.partial_cmp(&a).unwrap();
+    let tree = IntervalTreeNode::from_intervals_by(basic_tree_rectangles(), reversed);
+    let natural = basic_tree().to_structure();
+    let mirrored = tree.to_structure();
+    assert_eq!(mirrored.center_val, natural.center_val);
+    assert_eq!(
+        mirrored.lt_nodes.unwrap().center_val,
+        natural.gt_nodes.unwrap().center_val
+    );
+    assert_eq!(
+        mirrored.gt_nodes.unwrap().center_val,
+        natural.lt_nodes.unwrap().center_val
+    );
+
+    let queries = [
+        Rectangle::new(1.0, 4.0, 2.5, 6.0),
+        Rectangle::new(1.0, 1.0, 2.0, 2.0),
+        Rectangle::new(-10.0, -6.0, 0.0, 1.0),
+    ];
+    let basic_tree = basic_tree();
+    for q in &queries {
+        assert_eq!(
+            sorted_by_x(tree.range_search_by(q, reversed)),
+            sorted_by_x(basic_tree.range_search(q))
+        );
+    }
+}
+
+fn random_rect(rng: &mut StdRng) -> Rectangle {
+    let range = Uniform::new(-10.0f64, 10.0);
+    let (xa, xb) = (rng.sample(range), rng.sample(range));
+    let (ya, yb) = (rng.sample(range), rng.sample(range));
+    Rectangle::new(xa.min(xb), xa.max(xb), ya.min(yb), ya.max(yb))
+}
+
+#[test]
+fn test_tree_nearest_to() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let rectangles = (0..50)
+        .map(|_| {
+            let r = random_rect(&mut rng);
+            // Shrink the rectangles so that many queries do not overlap any of them
+            Rectangle::new(r.xmin, r.xmin + 0.5, r.ymin, r.ymin + 0.5)
+        })
+        .collect::<Vec<_>>();
+    let tree = IntervalTreeNode::from_intervals(rectangles.clone());
+
+    for _ in 0..100 {
+        let q = random_rect(&mut rng);
+        let q = Rectangle::new(q.xmin, q.xmin + 0.2, q.ymin, q.ymin + 0.2);
+        let nearest = tree.nearest_to(&q).unwrap();
+        let brute_force = rectangles
+            .iter()
+            .map(|r| distance_sq(r, &q))
+            .fold(f64::INFINITY, f64::min);
+        assert_eq!(distance_sq(nearest, &q), brute_force);
+    }
+}
+
+#[derive(Clone, Debug)]
+struct LabeledRectangle {
+    rect: Rectangle,
+    label: String,
+}
+
+impl Interval<2> for LabeledRectangle {
+    type Scalar = f64;
+    fn min_at(&self, k: usize) -> f64 {
+        self.rect.min_at(k)
+    }
+
+    fn max_at(&self, k: usize) -> f64 {
+        self.rect.max_at(k)
+    }
+}
+
+#[test]
+fn test_tree_map() {
+    let tree = basic_tree().map(|rect| LabeledRectangle {
+        label: format!("{}", rect.xmin),
+        rect,
+    });
+    assert_eq!(tree.stats(), basic_tree().stats());
+
+    let rect = Rectangle::new(1.0, 4.0, 2.5, 6.0);
+    let mut labels = tree
+        .range_search(&rect)
+        .into_iter()
+        .map(|r| r.label.as_str())
+        .collect::<Vec<_>>();
+    labels.sort();
+    assert_eq!(labels, vec!["-5", "2", "3"]);
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct UnsignedRectangle([(u32, u32); 2]);
+
+impl Interval<2> for UnsignedRectangle {
+    type Scalar = u32;
+    fn min_at(&self, k: usize) -> u32 {
+        self.0[k].0
+    }
+
+    fn max_at(&self, k: usize) -> u32 {
+        self.0[k].1
+    }
+}
+
+#[test]
+fn test_unsigned_scalars() {
+    let a = UnsignedRectangle([(0, 4), (2, 3)]);
+    let b = UnsignedRectangle([(5, 8), (0, 10)]);
+    let c = UnsignedRectangle([(4, 4), (0, 10)]);
+    assert_eq!(a.try_overlapping_volume(&b), None);
+    assert_eq!(b.try_overlapping_volume(&a), None);
+    assert_eq!(a.try_overlapping_volume(&c), Some(0));
+    assert_eq!(c.try_overlapping_volume(&b), None);
+
+    // Would overflow with (min + max) / 2
+    let huge = UnsignedRectangle([(u32::MAX - 2, u32::MAX), (u32::MAX - 1, u32::MAX)]);
+    assert_eq!(huge.avg_at(0), u32::MAX - 1);
+    assert_eq!(huge.avg_at(1), u32::MAX - 1);
+
+    let tree = IntervalTreeNode::from_intervals(vec![a.clone(), b, c, huge.clone()]);
+    let found = tree.range_search(&UnsignedRectangle([(u32::MAX, u32::MAX), (0, u32::MAX)]));
+    assert_eq!(found, vec![&huge]);
+    let found = tree.range_search(&UnsignedRectangle([(0, 3), (0, 2)]));
+    assert_eq!(found, vec![&a]);
+}
+
+#[cfg(feature = "io")]
+#[test]
+fn test_tree_from_reader() {
+    let bytes = basic_tree_rectangles()
+        .iter()
+        .flat_map(|r| [r.xmin, r.xmax, r.ymin, r.ymax])
+        .flat_map(f64::to_le_bytes)
+        .collect::<Vec<_>>();
+    let parse = |record: &[u8]| {
+        let v = |i: usize| f64::from_le_bytes(record[8 * i..8 * (i + 1)].try_into().unwrap());
+        Rectangle::new(v(0), v(1), v(2), v(3))
+    };
+
+    let tree = IntervalTreeNode::from_reader(std::io::Cursor::new(&bytes), 32, parse).unwrap();
+    assert!(tree == basic_tree());
+
+    let truncated = std::io::Cursor::new(&bytes[..bytes.len() - 1]);
+    let err = IntervalTreeNode::<Rectangle, 2>::from_reader(truncated, 32, parse);
+    assert_eq!(err.err().unwrap().kind(), std::io::ErrorKind::InvalidData);
+    let empty = std::io::Cursor::new(&[]);
+    let err = IntervalTreeNode::<Rectangle, 2>::from_reader(empty, 32, parse);
+    assert_eq!(err.err().unwrap().kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_tree_leaf_groups() {
+    let tree = basic_tree();
+    let groups = tree.leaf_groups();
+    assert_eq!(groups.len(), 4);
+    assert_eq!(
+        groups.iter().map(Vec::len).collect::<Vec<_>>(),
+        vec![1, 2, 1, 1]
+    );
+    let flattened = groups.into_iter().flatten().collect::<Vec<_>>();
+    assert_eq!(sorted_by_x(flattened), sorted_by_x(tree.iter().collect()));
+}
+
+#[test]
+fn test_tree_median_bias() {
+    // Most of the intervals end or start exactly at the median, 5
+    let segments = (1..=4)
+        .map(|i| Segment(5 - i, 5))
+        .chain((1..=4).map(|i| Segment(5, 5 + i)))
+        .chain(std::iter::once(Segment(5, 5)))
+        .collect::<Vec<_>>();
+
+    let center_len = |bias| {
+        let tree = IntervalTreeNode::from_intervals_biased(segments.clone(), bias);
+        for p in 0..12 {
+            let q = Segment(p, p);
+            let mut found = tree.range_search(&q);
+            let mut expected = segments
+                .iter()
+                .filter(|s| s.overlaps(&q))
+                .collect::<Vec<_>>();
+            found.sort_by_key(|s| (s.0, s.1));
+            expected.sort_by_key(|s| (s.0, s.1));
+            assert_eq!(found, expected, "{bias:?} {p}");
+        }
+        assert!(tree == IntervalTreeNode::from_intervals(segments.clone()));
+        let info = tree.to_structure();
+        assert_eq!(info.center_val, 5);
+        info.center_len
+    };
+    assert_eq!(center_len(MedianBias::Center), 9);
+    assert_eq!(center_len(MedianBias::Left), 4);
+    assert_eq!(center_len(MedianBias::Right), 4);
+}
+
+#[test]
+fn test_tree_iter_by_distance() {
+    let mut rng = StdRng::seed_from_u64(1);
+    let rectangles = (0..100)
+        .map(|_| {
+            let r = random_rect(&mut rng);
+            Rectangle::new(r.xmin, r.xmin + 1.0, r.ymin, r.ymin + 1.0)
+        })
+        .collect::<Vec<_>>();
+    let tree = IntervalTreeNode::from_intervals(rectangles.clone());
+
+    for p in [(0.0, 0.0), (3.5, -8.0), (20.0, 20.0)] {
+        let mut brute_force = rectangles
+            .iter()
+            .map(|r| distance_sq(r, &p))
+            .collect::<Vec<_>>();
+        brute_force.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let distances = tree
+            .iter_by_distance(&p)
+            .map(|r| distance_sq(r, &p))
+            .collect::<Vec<_>>();
+        assert_eq!(distances, brute_force);
+        assert_eq!(
+            distance_sq(tree.iter_by_distance(&p).next().unwrap(), &p),
+            distance_sq(tree.nearest_to(&p).unwrap(), &p)
+        );
+    }
+}
+
+#[test]
+fn test_tree_from_intervals_reported() {
+    let (tree, report) = IntervalTreeNode::from_intervals_reported(basic_tree_rectangles());
+    let stats = tree.stats();
+    assert_eq!(report.nodes, stats.nodes);
+    assert_eq!(report.max_depth, stats.height);
+    assert_eq!(
+        report.largest_leaf,
+        tree.leaf_groups().iter().map(Vec::len).max().unwrap()
+    );
+
+    // Segments all containing 0, plus one on each side to make sure the median is 0
+    let segments = vec![
+        Segment(-5, 5),
+        Segment(-1, 1),
+        Segment(0, 2),
+        Segment(-10, -8),
+        Segment(8, 10),
+    ];
+    let (_, report) = IntervalTreeNode::from_intervals_reported(segments);
+    assert_eq!(
+        report,
+        BuildReport {
+            nodes: 3,
+            max_depth: 2,
+            largest_leaf: 3,
+            straddling: 2,
+        }
+    );
+}
+
+#[test]
+fn test_tree_range_search_multi() {
+    let mut rng = StdRng::seed_from_u64(2);
+    let rectangles = (0..200).map(|_| random_rect(&mut rng)).collect::<Vec<_>>();
+    let tree = IntervalTreeNode::from_intervals(rectangles);
+    let queries = (0..5)
+        .map(|_| {
+            let r = random_rect(&mut rng);
+            Rectangle::new(r.xmin, r.xmin + 1.0, r.ymin, r.ymin + 1.0)
+        })
+        .collect::<Vec<_>>();
+
+    let mut expected = Vec::new();
+    for q in &queries {
+        for r in tree.range_search(q) {
+            if !expected.iter().any(|e| std::ptr::eq(*e, r)) {
+                expected.push(r);
+            }
+        }
+    }
+    let multi = tree.range_search_multi(&queries);
+    assert_eq!(multi.len(), expected.len());
+    assert!(expected
+        .iter()
+        .all(|e| multi.iter().any(|r| std::ptr::eq(*e, *r))));
+    assert!(tree.range_search_multi::<Rectangle>(&[]).is_empty());
+}
+
+#[test]
+fn test_tree_from_intervals_max_depth() {
+    let mut rng = StdRng::seed_from_u64(3);
+    let rectangles = (0..200).map(|_| random_rect(&mut rng)).collect::<Vec<_>>();
+    let tree = IntervalTreeNode::from_intervals(rectangles.clone());
+    for max_depth in [0, 1, 3] {
+        let bounded = IntervalTreeNode::from_intervals_max_depth(rectangles.clone(), max_depth);
+        assert!(bounded.height() <= max_depth + 1);
+        assert!(bounded.height() <= tree.height());
+        assert_eq!(bounded.stats().intervals, rectangles.len());
+        for _ in 0..20 {
+            let q = random_rect(&mut rng);
+            assert_eq!(
+                sorted_by_x(bounded.range_search(&q)),
+                sorted_by_x(tree.range_search(&q))
+            );
+        }
+    }
+}
+
+fn assert_send_sync<T: Send + Sync>() {}
+
+fn assert_send<T: Send>() {}
+
+#[test]
+fn test_tree_send_sync() {
+    assert_send_sync::<IntervalTreeNode<Rectangle, 2>>();
+    assert_send_sync::<IntervalTreeNode<DynInterval, 0>>();
+    assert_send::<crate::iter::IntervalTreeIterator<'_, Rectangle, 2>>();
+
+    let tree = basic_tree();
+    let q = Rectangle::new(1.0, 4.0, 1.0, 4.0);
+    let expected = tree.range_search(&q).len();
+    std::thread::scope(|s| {
+        let handles = (0..4)
+            .map(|_| s.spawn(|| tree.range_search(&q).len()))
+            .collect::<Vec<_>>();
+        for h in handles {
+            assert_eq!(h.join().unwrap(), expected);
+        }
+    });
+}
+
+#[test]
+fn test_tree_range_search_sorted() {
+    let rect = Rectangle::new(1.0, 4.0, 2.5, 6.0);
+    let tree = basic_tree();
+    let mut manual = tree.range_search(&rect);
+    manual.sort_by(|a, b| a.avg_at(0).partial_cmp(&b.avg_at(0)).unwrap());
+    assert_eq!(
+        tree.range_search_sorted(&rect, SortKey::ByDimension(0)),
+        manual
+    );
+
+    let by_volume = tree.range_search_sorted(&rect, SortKey::ByOverlapVolume);
+    assert_eq!(by_volume.len(), 3);
+    assert!(by_volume
+        .windows(2)
+        .all(|w| w[0].overlapping_volume(&rect) >= w[1].overlapping_volume(&rect)));
+}
+
+#[test]
+fn test_tree_sum_weights() {
+    let mut rng = StdRng::seed_from_u64(4);
+    let rectangles = (0..200).map(|_| random_rect(&mut rng)).collect::<Vec<_>>();
+    let tree = IntervalTreeNode::from_intervals(rectangles);
+    for _ in 0..20 {
+        let q = random_rect(&mut rng);
+        let count = tree.range_search(&q).len();
+        assert_eq!(tree.count_overlapping(&q), count);
+        assert_eq!(tree.sum_weights(&q, |_| 1u32), count as u32);
+        let areas = tree
+            .range_search(&q)
+            .iter()
+            .map(|r| (r.xmax - r.xmin) * (r.ymax - r.ymin))
+            .sum::<f64>();
+        let weighted = tree.sum_weights(&q, |r| (r.xmax - r.xmin) * (r.ymax - r.ymin));
+        assert!((weighted - areas).abs() < 1e-9);
+    }
+}
+
+#[test]
+fn test_tree_all_straddling() {
+    let n = 10_000;
+    let rectangles = (0..n)
+        .map(|i| {
+            let d = i as f64 / n as f64;
+            Rectangle::new(-10.0 - d, 10.0 + d, -10.0 + d, 10.0 - d)
+        })
+        .collect::<Vec<_>>();
+    let tree = IntervalTreeNode::from_intervals(rectangles.clone());
+    assert_eq!(tree.height(), 1);
+    assert_eq!(tree.stats().nodes, 1);
+    for p in [(0.0, 0.0), (10.5, 0.0), (0.0, 9.5), (0.0, 11.0)] {
+        let expected = rectangles.iter().filter(|r| r.overlaps(&p)).count();
+        assert_eq!(tree.range_search(&p).len(), expected);
+    }
+    assert_eq!(tree.range_search(&(0.0, 0.0)).len(), n);
+    assert!(tree.range_search(&(0.0, 11.0)).is_empty());
+}
+
+#[test]
+fn test_tree_ranges() {
+    let ranges = vec![0.0..5.0, 4.0..6.0, -3.0..-1.0, 5.5..10.0, 2.0..2.5];
+    let tree = IntervalTreeNode::<std::ops::Range<f64>, 1>::from_intervals(ranges);
+    let mut found = tree.range_search(&(4.5..5.6));
+    found.sort_by(|a, b| a.start.partial_cmp(&b.start).unwrap());
+    assert_eq!(found, vec![&(0.0..5.0), &(4.0..6.0), &(5.5..10.0)]);
+    // Bounds are inclusive, even for Range
+    assert_eq!(tree.range_search(&(-1.0..0.0)).len(), 2);
+    assert!(tree.range_search(&(-0.5..-0.1)).is_empty());
+
+    let tree = IntervalTreeNode::from_intervals(vec![0..=2u32, 3..=5, 6..=9]);
+    assert_eq!(tree.range_search(&(2..=3)).len(), 2);
+    assert_eq!(tree.range_search(&(10..=12)).len(), 0);
+}
+
+#[test]
+fn test_interval_contains_point() {
+    let rect = Rectangle::new(1.0, 4.0, 2.5, 6.0);
+    assert!(rect.contains_point(&(2.0, 3.0)));
+    assert!(rect.contains_point(&(1.0, 6.0)));
+    assert!(rect.contains_point(&(4.0, 2.5)));
+    assert!(!rect.contains_point(&(4.1, 3.0)));
+    assert!(!rect.contains_point(&(2.0, 2.4)));
+}
+
+#[test]
+fn test_tree_validate_invariants() {
+    let mut rng = StdRng::seed_from_u64(5);
+    let rectangles = (0..100).map(|_| random_rect(&mut rng)).collect::<Vec<_>>();
+    assert_eq!(
+        IntervalTreeNode::from_intervals(rectangles.clone()).validate_invariants(),
+        Ok(())
+    );
+    for bias in [MedianBias::Left, MedianBias::Right] {
+        let tree = IntervalTreeNode::from_intervals_biased(rectangles.clone(), bias);
+        assert_eq!(tree.validate_invariants(), Ok(()));
+    }
+
+    // The lt child of the root ends at 1.0
+    let mut tree = basic_tree();
+    tree.center_val = 0.5;
+    assert_eq!(
+        tree.validate_invariants(),
+        Err(InvariantError::MisplacedInterval {
+            k: 0,
+            side: Ordering::Less
+        })
+    );
+
+    let mut tree = basic_tree();
+    tree.gt_nodes.as_mut().unwrap().k = 1;
+    assert_eq!(
+        tree.validate_invariants(),
+        Err(InvariantError::InvalidSplitDimension {
+            expected: 0,
+            found: 1
+        })
+    );
+}
+
+#[test]
+fn test_interval_translated_scaled() {
+    let tree = basic_tree();
+    let rect = Rectangle::new(-2.0, 1.0, 0.5, 4.0);
+    let query = BoxInterval::from(rect.translated([3.0, 2.0]));
+    assert_eq!(query, BoxInterval([(1.0, 4.0), (2.5, 6.0)]));
+    let shifted = Rectangle::new(1.0, 4.0, 2.5, 6.0);
+    assert_eq!(
+        sorted_by_x(tree.range_search(&query)),
+        sorted_by_x(tree.range_search(&shifted))
+    );
+    assert_eq!(tree.range_search(&query).len(), 3);
+
+    assert_eq!(rect.scaled(2.0), [(-4.0, 2.0), (1.0, 8.0)]);
+    assert_eq!(rect.scaled(-1.0), [(-1.0, 2.0), (-4.0, -0.5)]);
+}
+
+#[test]
+fn test_tree_from_intervals_indexed() {
+    let mut rng = StdRng::seed_from_u64(6);
+    let rectangles = (0..100).map(|_| random_rect(&mut rng)).collect::<Vec<_>>();
+    let tree = IntervalTreeNode::from_intervals_indexed(rectangles.clone());
+    for _ in 0..20 {
+        let q = random_rect(&mut rng);
+        let found = tree.range_search(&q);
+        assert_eq!(
+            found.len(),
+            rectangles.iter().filter(|r| r.overlaps(&q)).count()
+        );
+        for i in found {
+            assert_eq!(&rectangles[i.index()], i.interval());
+        }
+    }
+    let mut indices = tree.iter().map(|i| i.index()).collect::<Vec<_>>();
+    indices.sort();
+    assert_eq!(indices, (0..rectangles.len()).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_tree_prune_empty() {
+    // Nested segments get split into chains of nodes, with a single interval each
+    let segments = (0..64)
+        .map(|i| Segment(i * 10, i * 10 + 5))
+        .collect::<Vec<_>>();
+    let mut tree = IntervalTreeNode::from_intervals(segments);
+    // Emptying most centers leaves chains of nodes with a single child
+    tree.retain(|s| s.0 % 70 == 0);
+    let kept = tree.iter().cloned().collect::<Vec<_>>();
+    let before = tree.stats();
+
+    tree.prune_empty();
+    let after = tree.stats();
+    assert!(after.nodes < before.nodes);
+    assert!(after.height < before.height);
+    assert_eq!(tree.iter().cloned().collect::<Vec<_>>(), kept);
+    for q in [
+        Segment(0, 1000),
+        Segment(-5, 0),
+        Segment(140, 150),
+        Segment(141, 149),
+    ] {
+        let mut expected = kept.iter().filter(|s| s.overlaps(&q)).collect::<Vec<_>>();
+        let mut found = tree.range_search(&q);
+        expected.sort_by_key(|s| s.0);
+        found.sort_by_key(|s| s.0);
+        assert_eq!(found, expected);
+    }
+}
+
+#[test]
+fn test_temporal_tree() {
+    let tree = TemporalTree::new(vec![
+        (0.0, 10.0, "a"),
+        (5.0, 15.0, "b"),
+        (12.0, 13.0, "c"),
+        (20.0, 30.0, "d"),
+    ]);
+    let sorted = |v: Vec<&&'static str>| {
+        let mut v = v.into_iter().copied().collect::<Vec<_>>();
+        v.sort();
+        v
+    };
+    assert_eq!(sorted(tree.active_at(-1.0)), Vec::<&str>::new());
+    assert_eq!(sorted(tree.active_at(0.0)), vec!["a"]);
+    assert_eq!(sorted(tree.active_at(7.0)), vec!["a", "b"]);
+    assert_eq!(sorted(tree.active_at(12.5)), vec!["b", "c"]);
+    assert_eq!(sorted(tree.active_at(17.0)), Vec::<&str>::new());
+    assert_eq!(sorted(tree.active_during(9.0, 12.0)), vec!["a", "b", "c"]);
+    assert_eq!(sorted(tree.active_during(16.0, 19.0)), Vec::<&str>::new());
+    assert_eq!(sorted(tree.active_during(14.0, 40.0)), vec!["b", "d"]);
+    assert_eq!(tree.tree().iter().count(), 4);
+
+    assert!(matches!(
+        TemporalTree::<&str>::try_new(Vec::new()),
+        Err(BuildError::Empty)
+    ));
+    let tree = TemporalTree::try_new(vec![(0.0, 1.0, "a")]).unwrap();
+    assert_eq!(tree.active_at(0.5), vec![&"a"]);
+    assert!(matches!(
+        TemporalTree::try_new(vec![(0.0, f64::NAN, "a")]),
+        Err(BuildError::InvalidScalar { k: 0 })
+    ));
+}
+
+#[test]
+fn test_tree_range_search_brute_force() {
+    let mut rng = StdRng::seed_from_u64(7);
+    let rectangles = (0..500).map(|_| random_rect(&mut rng)).collect::<Vec<_>>();
+    let tree = IntervalTreeNode::from_intervals(rectangles.clone());
+    for _ in 0..50 {
+        let q = random_rect(&mut rng);
+        let expected = rectangles
+            .iter()
+            .filter(|r| r.overlaps(&q))
+            .collect::<Vec<_>>();
+        assert_eq!(sorted_by_x(tree.range_search(&q)), sorted_by_x(expected));
+        let found = tree.range_search(&q);
+        assert_eq!(
+            found,
+            tree.range_search_by(&q, |a: f64, b| a.partial_cmp(&b).unwrap())
+        );
+    }
+}
+
+#[test]
+fn test_tree_from_id_intervals() {
+    use rand::seq::SliceRandom;
+
+    let mut rng = StdRng::seed_from_u64(8);
+    let mut ids = (0..100u64).map(|i| i * 7 + 1000).collect::<Vec<_>>();
+    ids.shuffle(&mut rng);
+    let items = ids
+        .iter()
+        .map(|&id| (random_rect(&mut rng), id))
+        .collect::<Vec<_>>();
+    let tree = IntervalTreeNode::from_id_intervals(items.clone());
+    for _ in 0..20 {
+        let q = random_rect(&mut rng);
+        let mut found = tree.range_search_ids(&q);
+        let mut expected = items
+            .iter()
+            .filter(|(r, _)| r.overlaps(&q))
+            .map(|(_, id)| *id)
+            .collect::<Vec<_>>();
+        found.sort();
+        expected.sort();
+        assert_eq!(found, expected);
+    }
+    for i in tree.iter() {
+        assert!(items.contains(&(i.interval().clone(), i.id())));
+    }
+}
+
+#[test]
+fn test_tree_range_search_with_volume() {
+    let rect = Rectangle::new(1.0, 4.0, 2.5, 6.0);
+    let tree = basic_tree();
+    let mut found = tree.range_search_with_volume(&rect);
+    assert_eq!(found.len(), 3);
+    found.sort_by(|a, b| a.0.avg_at(0).partial_cmp(&b.0.avg_at(0)).unwrap());
+    assert_approx(found[0].0, Rectangle::new(-5.0, 1.0, 2.0, 4.0));
+    assert_eq!(
+        found.iter().map(|(_, v)| *v).collect::<Vec<_>>(),
+        vec![0.0, 1.0, 0.5]
+    );
+}
+
+#[test]
+fn test_tree_densest_leaves() {
+    // 20 segments containing 50, which all end up in the root leaf, plus spread out ones
+    let mut segments = (0..20).map(|i| Segment(50 - i, 50 + i)).collect::<Vec<_>>();
+    segments.extend((0..10).map(|i| Segment(i * 3, i * 3 + 1)));
+    segments.extend((0..10).map(|i| Segment(80 + i * 3, 80 + i * 3 + 1)));
+    let tree = IntervalTreeNode::from_intervals(segments);
+
+    let densest = tree.densest_leaves(3);
+    assert_eq!(densest.len(), 3);
+    assert_eq!(densest[0].1, 20);
+    assert_eq!(densest[0].0.len(), 20);
+    assert!(densest[0].0.iter().all(|s| s.0 <= 50 && 50 <= s.1));
+    assert!(densest.windows(2).all(|w| w[0].1 >= w[1].1));
+
+    let leaves = tree.leaf_groups().len();
+    assert_eq!(tree.densest_leaves(1000).len(), leaves);
+    assert!(tree.densest_leaves(0).is_empty());
+}
+
+#[test]
+fn test_tree_iter_rev() {
+    let mut rng = StdRng::seed_from_u64(9);
+    let rectangles = (0..200).map(|_| random_rect(&mut rng)).collect::<Vec<_>>();
+    let tree = IntervalTreeNode::from_intervals(rectangles);
+    let forward = tree.iter().collect::<Vec<_>>();
+    let mut backward = tree.iter().rev().collect::<Vec<_>>();
+    backward.reverse();
+    assert_eq!(forward, backward);
+
+    // Alternating both ends yields every interval exactly once
+    let mut it = tree.iter();
+    let (mut front, mut back) = (Vec::new(), Vec::new());
+    for step in 0.. {
+        let next = if step % 3 == 0 {
+            it.next_back()
+        } else {
+            it.next()
+        };
+        match next {
+            Some(i) if step % 3 == 0 => back.push(i),
+            Some(i) => front.push(i),
+            None => break,
+        }
+    }
+    assert!(it.next().is_none() && it.next_back().is_none());
+    back.reverse();
+    front.extend(back);
+    assert_eq!(front, forward);
+}
+
+#[test]
+fn test_tree_iter_size_hint() {
+    let tree = basic_tree();
+    assert_eq!(tree.len(), 5);
+    assert!(!tree.is_empty());
+    let mut it = tree.iter();
+    assert_eq!(it.size_hint(), (5, Some(5)));
+    it.next();
+    assert_eq!(it.size_hint(), (4, Some(4)));
+    it.next_back();
+    assert_eq!(it.len(), 3);
+    assert_eq!(it.by_ref().count(), 3);
+    assert_eq!(it.size_hint(), (0, Some(0)));
+
+    let mut tree = basic_tree();
+    tree.retain(|_| false);
+    assert!(tree.is_empty());
+    assert_eq!(tree.iter().len(), 0);
+}
+
+#[test]
+fn test_tree_iter_fused() {
+    fn assert_fused<T: std::iter::FusedIterator>(_: &T) {}
+
+    let tree = basic_tree();
+    let mut it = tree.iter();
+    assert_fused(&it);
+    assert_eq!(it.by_ref().count(), 5);
+    for _ in 0..5 {
+        assert!(it.next().is_none());
+        assert!(it.next_back().is_none());
+    }
+}
+
+#[test]
+fn test_tree_range_search_buf() {
+    let tree = basic_tree();
+    let mut out = Vec::new();
+    let rect = Rectangle::new(1.0, 4.0, 2.5, 6.0);
+    tree.range_search_buf(&rect, &mut out);
+    assert_eq!(out, tree.range_search(&rect));
+    assert_eq!(out.len(), 3);
+
+    let capacity = out.capacity();
+    tree.range_search_buf(&(2.5, 5.5), &mut out);
+    assert_eq!(out, tree.range_search(&(2.5, 5.5)));
+    assert_eq!(out.len(), 1);
+    assert_eq!(out.capacity(), capacity);
+}
+
+#[test]
+fn test_tree_periodic() {
+    // Longitudes on x, latitudes on y
+    let lon = |xmin, xmax| Periodic::new(Rectangle::new(xmin, xmax, -10.0, 10.0), 0);
+    let pacific = lon(170.0, -170.0);
+    let fiji = lon(-179.0, -175.0);
+    let europe = lon(-10.0, 30.0);
+    // Only overlaps with the pacific when wraparound is considered
+    assert!(!pacific.interval().overlaps(fiji.interval()));
+    assert!(pacific.overlaps(&fiji));
+    assert!(fiji.overlaps(&pacific));
+    assert!(!pacific.overlaps(&europe));
+    assert!(pacific.contains_point(&(179.0, 0.0)));
+    assert!(!pacific.contains_point(&(0.0, 0.0)));
+
+    let mut intervals = vec![pacific.clone(), fiji.clone(), europe.clone()];
+    intervals.extend((0..20).map(|i| lon(-150.0 + i as f64 * 15.0, -145.0 + i as f64 * 15.0)));
+    let tree = IntervalTreeNode::from_intervals(intervals.clone());
+    let brute_force = |q: &Periodic<Rectangle>| intervals.iter().filter(|i| i.overlaps(q)).count();
+    for q in [
+        lon(178.0, 179.0),
+        lon(-179.5, -179.0),
+        lon(175.0, -178.0),
+        lon(0.0, 10.0),
+        lon(100.0, -100.0),
+    ] {
+        assert_eq!(tree.range_search(&q).len(), brute_force(&q));
+    }
+    let found = tree.range_search(&lon(178.0, 179.0));
+    assert_eq!(found, vec![&pacific]);
+    assert_eq!(tree.range_search(&(-177.0, 0.0)).len(), 2);
+    assert_eq!(tree.range_search(&lon(175.0, -178.0)).len(), 2);
+}
+
+#[test]
+fn test_tree_periodic_unsigned() {
+    // Degrees on 0..360, 350..=10 wrapping around 0
+    let deg = |min: u16, max: u16| Periodic::new(min..=max, 0);
+    let north = deg(350, 10);
+    assert_eq!(Interval::<1>::avg_at(&north, 0), 180);
+    assert_eq!(Interval::<1>::avg_at(&deg(10, 20), 0), 15);
+
+    let mut intervals = vec![north.clone(), deg(300, 40), deg(5, 15)];
+    intervals.extend((0..30u16).map(|i| deg(i * 12, i * 12 + 6)));
+    let tree = IntervalTreeNode::from_intervals(intervals.clone());
+    for q in [
+        deg(355, 359),
+        deg(0, 3),
+        deg(340, 2),
+        deg(100, 120),
+        deg(359, 0),
+    ] {
+        let expected = intervals.iter().filter(|i| i.overlaps(&q)).count();
+        assert_eq!(tree.range_search(&q).len(), expected);
+    }
+    assert!(tree.range_search(&deg(355, 359)).contains(&&north));
+}
+
+#[test]
+fn test_interval_center_point() {
+    let rect = Rectangle::new(0.0, 4.0, -3.0, 2.0);
+    assert_eq!(rect.center_point(), [2.0, -0.5]);
+    assert_eq!(rect.center_point_vec(), vec![2.0, -0.5]);
+    let dyn_interval = DynInterval(vec![(0.0, 4.0), (-3.0, 2.0), (1.0, 1.0)]);
+    assert_eq!(dyn_interval.center_point_vec(), vec![2.0, -0.5, 1.0]);
+}
+
+#[test]
+fn test_tree_best_overlap() {
+    let tree = basic_tree();
+    let rect = Rectangle::new(1.0, 4.0, 2.5, 6.0);
+    let (best, volume) = tree.best_overlap(&rect).unwrap();
+    let brute_force = basic_tree_rectangles()
+        .into_iter()
+        .filter(|r| r.overlaps(&rect))
+        .max_by(|a, b| {
+            a.overlapping_volume(&rect)
+                .partial_cmp(&b.overlapping_volume(&rect))
+                .unwrap()
+        })
+        .unwrap();
+    assert_approx(best, &brute_force);
+    assert_approx(best, Rectangle::new(2.0, 3.0, 5.0, 6.0));
+    assert_eq!(volume, 1.0);
+    assert!(tree.best_overlap(&(100.0, 100.0)).is_none());
+}
+
+#[test]
+fn test_tree_from_intervals_tie_aware() {
+    // Nested segments, all centered on 0
+    let segments = (1..200).map(|i| Segment(-i, i)).collect::<Vec<_>>();
+    let naive = IntervalTreeNode::from_intervals(segments.clone());
+    let tie_aware = IntervalTreeNode::from_intervals_tie_aware(segments.clone());
+    let largest_leaf = |t: &IntervalTreeNode<Segment, 1>| t.densest_leaves(1)[0].1;
+    // The naive build cannot split them at all
+    assert_eq!(largest_leaf(&naive), segments.len());
+    // The longest half of them still straddles any split point keeping the tree balanced
+    assert!(largest_leaf(&tie_aware) <= segments.len() / 2 + 1);
+    assert!(tie_aware.stats().leaves > naive.stats().leaves);
+    assert_eq!(tie_aware.validate_invariants(), Ok(()));
+
+    for q in [
+        Segment(0, 0),
+        Segment(50, 60),
+        Segment(-199, -199),
+        Segment(150, 300),
+        Segment(200, 300),
+    ] {
+        let mut expected = segments
+            .iter()
+            .filter(|s| s.overlaps(&q))
+            .collect::<Vec<_>>();
+        let mut found = tie_aware.range_search(&q);
+        expected.sort_by_key(|s| s.1);
+        found.sort_by_key(|s| s.1);
+        assert_eq!(found, expected);
+    }
+
+    let mut rng = StdRng::seed_from_u64(10);
+    let rectangles = (0..200).map(|_| random_rect(&mut rng)).collect::<Vec<_>>();
+    let tree = IntervalTreeNode::from_intervals_tie_aware(rectangles.clone());
+    for _ in 0..20 {
+        let q = random_rect(&mut rng);
+        let expected = rectangles
+            .iter()
+            .filter(|r| r.overlaps(&q))
+            .collect::<Vec<_>>();
+        assert_eq!(sorted_by_x(tree.range_search(&q)), sorted_by_x(expected));
+    }
+}
+
+#[test]
+fn test_tree_cursor() {
+    let tree = basic_tree();
+    let root = tree.cursor();
+    assert_eq!(root.split_dimension(), 0);
+    assert!(root.leaf_intervals().is_none());
+
+    let left = root.go_left().unwrap();
+    assert!(left.go_left().is_none() && left.go_right().is_none());
+    let intervals = left.leaf_intervals().unwrap();
+    assert_eq!(intervals.len(), 1);
+    assert_approx(&intervals[0], Rectangle::new(-5.0, 1.0, 2.0, 4.0));
+    assert!(left.go_center().is_err());
+
+    let center = root.go_center().ok().unwrap();
+    assert_eq!(center.split_dimension(), 1);
+    assert!(center.go_left().is_none());
+    assert_eq!(center.leaf_intervals().unwrap().len(), 2);
+    let intervals = center.go_right().unwrap().leaf_intervals().unwrap();
+    assert_eq!(intervals.len(), 1);
+    assert_approx(&intervals[0], Rectangle::new(2.0, 3.0, 5.0, 6.0));
+    assert!(intervals[0].max_at(1) > center.center_value());
+}
+
+#[test]
+fn test_tree_from_refs() {
+    let mut rng = StdRng::seed_from_u64(11);
+    let rectangles = (0..200).map(|_| random_rect(&mut rng)).collect::<Vec<_>>();
+    let tree = IntervalTreeNode::from_refs(&rectangles);
+    assert_eq!(tree.len(), rectangles.len());
+    for _ in 0..20 {
+        let q = random_rect(&mut rng);
+        let expected = rectangles
+            .iter()
+            .filter(|r| r.overlaps(&q))
+            .collect::<Vec<_>>();
+        let found = tree.range_search(&q).into_iter().map(|r| r.get()).collect();
+        assert_eq!(sorted_by_x(found), sorted_by_x(expected));
+    }
+    // The matches point into the caller's vector
+    let first = tree.range_search(&rectangles[0])[0].get();
+    assert!(rectangles.iter().any(|r| std::ptr::eq(r, first)));
+}
+
+#[test]
+fn test_tree_from_intervals_observed() {
+    let mut rng = StdRng::seed_from_u64(13);
+    let rectangles = (0..300).map(|_| random_rect(&mut rng)).collect::<Vec<_>>();
+    let mut events = Vec::new();
+    let tree = IntervalTreeNode::from_intervals_observed(rectangles.clone(), |e| events.push(e));
+    assert_eq!(events.len(), tree.stats().nodes);
+    // The root is the last node to be finalized
+    let root = events.last().unwrap();
+    assert_eq!(root.depth, 1);
+    assert_eq!(root.k, 0);
+    assert_eq!(root.intervals, rectangles.len());
+    assert_eq!(root.center_val, tree.cursor().center_value());
+    assert_eq!(events.iter().map(|e| e.depth).max(), Some(tree.height()));
+    let in_leaves: usize = events.iter().filter(|e| e.leaf).map(|e| e.center_len).sum();
+    assert_eq!(in_leaves, rectangles.len());
+}
+
+#[test]
+fn test_tree_range_search_cow() {
+    let tree = basic_tree();
+    let point = (1.0, 2.0);
+    let mut found = tree.range_search_cow(&point);
+    assert_eq!(found.len(), 3);
+    for (cow, i) in found.iter().zip(tree.range_search(&point)) {
+        match cow {
+            Cow::Borrowed(r) => assert!(std::ptr::eq(*r, i)),
+            Cow::Owned(_) => panic!("Results should be borrowed"),
+        }
+    }
+
+    found[0].to_mut().xmax += 10.0;
+    assert!(matches!(found[0], Cow::Owned(_)));
+    assert!(matches!(found[1], Cow::Borrowed(_)));
+    // The tree is left untouched
+    assert_eq!(found[0].xmax, tree.range_search(&point)[0].xmax + 10.0);
+}
+
+#[test]
+fn test_grid_range_search() {
+    let mut rng = StdRng::seed_from_u64(14);
+    let rectangles = (0..500).map(|_| random_rect(&mut rng)).collect::<Vec<_>>();
+    let tree = IntervalTreeNode::from_intervals(rectangles.clone());
+    for cells_per_dim in [1, 4, 16] {
+        let grid = IntervalGrid::from_intervals_grid(rectangles.clone(), cells_per_dim);
+        assert_eq!(grid.len(), rectangles.len());
+        for _ in 0..30 {
+            let q = random_rect(&mut rng);
+            assert_eq!(
+                sorted_by_x(grid.range_search(&q)),
+                sorted_by_x(tree.range_search(&q))
+            );
+        }
+        let point = (rng.gen_range(-10.0..10.0), rng.gen_range(-10.0..10.0));
+        assert_eq!(
+            sorted_by_x(grid.range_search(&point)),
+            sorted_by_x(tree.range_search(&point))
+        );
+    }
+
+    let empty = IntervalGrid::<Rectangle, 2>::from_intervals_grid(Vec::new(), 4);
+    assert!(empty.is_empty());
+    assert!(empty.range_search(&basic_tree_rectangles()[0]).is_empty());
+}
+
+#[test]
+fn test_tree_range_search_bounds() {
+    let tree = basic_tree();
+    let mut intervals = tree.range_search_bounds([1.0, 2.5], [4.0, 6.0]);
+    assert_eq!(intervals.len(), 3);
+    intervals.sort_by(|a, b| a.avg_at(0).partial_cmp(&b.avg_at(0)).unwrap());
+    assert_approx(intervals[0], Rectangle::new(-5.0, 1.0, 2.0, 4.0));
+    assert_approx(intervals[1], Rectangle::new(2.0, 3.0, 5.0, 6.0));
+    assert_approx(intervals[2], Rectangle::new(3.0, 7.0, 1.0, 3.0));
+    assert_eq!(
+        sorted_by_x(tree.range_search_bounds_dyn(&[1.0, 2.5], &[4.0, 6.0])),
+        intervals
+    );
+
+    let dyn_tree = IntervalTreeNode::from_intervals(dyn_tree_intervals());
+    let query = DynInterval(vec![(0.0, 1.0), (-1.0, 0.0), (1.0, 2.0)]);
+    let found = dyn_tree.range_search_bounds_dyn(&[0.0, -1.0, 1.0], &[1.0, 0.0, 2.0]);
+    assert!(!found.is_empty());
+    assert_eq!(found.len(), dyn_tree.range_search(&query).len());
+}
+
+#[test]
+fn test_managed_tree() {
+    let mut rng = StdRng::seed_from_u64(15);
+    let mut managed = ManagedIntervalTree::new(Vec::new());
+    assert!(managed.is_empty());
+    let mut rectangles = Vec::new();
+    // Sorted insertions would make the unmanaged tree a chain
+    for i in 0..2000 {
+        let x = i as f64 / 100.0;
+        let r = Rectangle::new(x, x + 0.005, 0.0, 1.0);
+        rectangles.push(r.clone());
+        managed.insert(r);
+        let ideal = (managed.len() as f64).log2().ceil().max(1.0);
+        assert!(managed.height() as f64 <= 2.0 * ideal + 2.0);
+    }
+    assert_eq!(managed.len(), rectangles.len());
+    assert!(managed.rebalances() > 0);
+    assert!(managed.mutations() < rectangles.len());
+
+    let mut unmanaged = IntervalTreeNode::from_intervals(vec![rectangles[0].clone()]);
+    for r in &rectangles[1..] {
+        unmanaged.insert(r.clone());
+    }
+    assert!(unmanaged.height() > 100);
+
+    for _ in 0..500 {
+        let r = rectangles.swap_remove(rng.gen_range(0..rectangles.len()));
+        assert_eq!(managed.remove(&r), Some(r.clone()));
+        assert_eq!(managed.remove(&r), None);
+    }
+    assert_eq!(managed.len(), rectangles.len());
+    for _ in 0..20 {
+        let q = random_rect(&mut rng);
+        let expected = rectangles
+            .iter()
+            .filter(|r| r.overlaps(&q))
+            .collect::<Vec<_>>();
+        assert_eq!(sorted_by_x(managed.range_search(&q)), sorted_by_x(expected));
+    }
+}
+
+#[test]
+fn test_tree_range_search_strict() {
+    let tree = basic_tree();
+    // Shares the x = 7.0 edge of [3, 7] x [1, 3] only
+    let touching = Rectangle::new(7.0, 9.0, 0.0, 2.0);
+    let found = tree.range_search(&touching);
+    assert_eq!(found.len(), 1);
+    assert_approx(found[0], Rectangle::new(3.0, 7.0, 1.0, 3.0));
+    assert!(tree.range_search_strict(&touching).is_empty());
+
+    let rect = Rectangle::new(1.0, 4.0, 2.5, 6.0);
+    // [-5, 1] x [2, 4] only touches rect on x = 1.0
+    let mut strict = tree.range_search_strict(&rect);
+    assert_eq!(strict.len(), 2);
+    strict.sort_by(|a, b| a.avg_at(0).partial_cmp(&b.avg_at(0)).unwrap());
+    assert_approx(strict[0], Rectangle::new(2.0, 3.0, 5.0, 6.0));
+    assert_approx(strict[1], Rectangle::new(3.0, 7.0, 1.0, 3.0));
+    assert!(tree.range_search_strict(&(1.0, 2.0)).is_empty());
+}
+
+/// Query counting how many times its bounds are read, i.e. how many intervals and nodes it is
+/// compared with.
+struct CountingQuery {
+    rect: Rectangle,
+    reads: std::cell::Cell<usize>,
+}
+
+impl Interval<2> for CountingQuery {
+    type Scalar = f64;
+    fn min_at(&self, k: usize) -> f64 {
+        self.reads.set(self.reads.get() + 1);
+        self.rect.min_at(k)
+    }
+
+    fn max_at(&self, k: usize) -> f64 {
+        self.reads.set(self.reads.get() + 1);
+        self.rect.max_at(k)
+    }
+}
+
+#[test]
+fn test_tree_intersecting() {
+    let mut rng = StdRng::seed_from_u64(16);
+    let rectangles = (0..2000)
+        .map(|_| {
+            let x = rng.gen_range(-100.0..100.0);
+            let y = rng.gen_range(-100.0..100.0);
+            Rectangle::new(x, x + 1.0, y, y + 1.0)
+        })
+        .collect::<Vec<_>>();
+    let tree = IntervalTreeNode::from_intervals(rectangles);
+    let query = CountingQuery {
+        rect: Rectangle::new(0.0, 10.0, 0.0, 10.0),
+        reads: std::cell::Cell::new(0),
+    };
+
+    let found = tree.intersecting(&query).collect::<Vec<_>>();
+    assert_eq!(found, tree.range_search(&query.rect));
+    assert!(!found.is_empty());
+    let pruned_reads = query.reads.take();
+
+    let scanned = tree.iter().filter(|i| i.overlaps(&query)).count();
+    assert_eq!(scanned, found.len());
+    assert!(pruned_reads * 4 < query.reads.take());
+
+    // Stopping early skips the rest of the traversal
+    let _ = tree.intersecting(&query).next();
+    assert!(query.reads.take() < pruned_reads);
+}
+
+#[test]
+fn test_tree_from_intervals_order() {
+    let mut rng = StdRng::seed_from_u64(17);
+    // Large intervals on x, all straddling its median: only y is discriminative
+    let rectangles = (0..500)
+        .map(|_| {
+            let x = rng.gen_range(-1.0..1.0);
+            let y = rng.gen_range(-100.0..100.0);
+            Rectangle::new(x - 100.0, x + 100.0, y, y + 1.0)
+        })
+        .collect::<Vec<_>>();
+    let natural = IntervalTreeNode::from_intervals(rectangles.clone());
+    let ordered = IntervalTreeNode::from_intervals_order(rectangles.clone(), [1, 0]);
+    assert_eq!(ordered.cursor().split_dimension(), 1);
+    assert!(ordered.height() < natural.height());
+    assert_eq!(ordered.validate_invariants(), Ok(()));
+
+    for _ in 0..30 {
+        let q = Rectangle::new(
+            rng.gen_range(-120.0..120.0),
+            rng.gen_range(-120.0..120.0),
+            rng.gen_range(-110.0..0.0),
+            rng.gen_range(0.0..110.0),
+        );
+        let q = Rectangle::new(q.xmin.min(q.xmax), q.xmin.max(q.xmax), q.ymin, q.ymax);
+        let expected = rectangles
+            .iter()
+            .filter(|r| r.overlaps(&q))
+            .collect::<Vec<_>>();
+        assert_eq!(sorted_by_x(ordered.range_search(&q)), sorted_by_x(expected));
+    }
+
+    let mut rectangles = (0..200).map(|_| random_rect(&mut rng)).collect::<Vec<_>>();
+    let ordered = IntervalTreeNode::from_intervals_order(rectangles.clone(), [1, 0]);
+    assert_eq!(ordered.validate_invariants(), Ok(()));
+    let q = random_rect(&mut rng);
+    rectangles.retain(|r| r.overlaps(&q));
+    assert_eq!(
+        sorted_by_x(ordered.range_search(&q)),
+        sorted_by_x(rectangles.iter().collect())
+    );
+}
+
+#[test]
+fn test_tree_dimension_transition_count() {
+    // Only the center of the root is a subtree, the other nodes holding single leaves
+    assert_eq!(basic_tree().dimension_transition_count(), 1);
+    let single = IntervalTreeNode::from_intervals(vec![Rectangle::new(0.0, 1.0, 0.0, 1.0)]);
+    assert_eq!(single.dimension_transition_count(), 0);
+    let dyn_tree = IntervalTreeNode::from_intervals(dyn_tree_intervals());
+    assert!(dyn_tree.dimension_transition_count() <= dyn_tree.stats().nodes);
+}
+
+#[test]
+fn test_tree_try_from_intervals() {
+    let tree = IntervalTreeNode::try_from_intervals(basic_tree_rectangles()).unwrap();
+    assert_eq!(tree.len(), basic_tree_rectangles().len());
+
+    assert_eq!(
+        IntervalTreeNode::<Rectangle, 2>::try_from_intervals(Vec::new()).err(),
+        Some(BuildError::Empty)
+    );
+
+    let mut intervals = dyn_tree_intervals();
+    intervals.push(DynInterval(vec![(0.0, 1.0), (0.0, 1.0)]));
+    assert_eq!(
+        IntervalTreeNode::try_from_intervals(intervals).err(),
+        Some(BuildError::DimensionMismatch {
+            expected: 3,
+            found: 2
+        })
+    );
+
+    let mut rectangles = basic_tree_rectangles();
+    rectangles[2].ymax = f64::NAN;
+    let error = IntervalTreeNode::try_from_intervals(rectangles)
+        .err()
+        .unwrap();
+    assert_eq!(error, BuildError::InvalidScalar { k: 1 });
+    assert_eq!(
+        error.to_string(),
+        "an interval has a bound on dimension 1 which cannot be compared"
+    );
+}
+
+#[test]
+fn test_tree_range_search_filter() {
+    let tree = basic_tree();
+    let rect = Rectangle::new(1.0, 4.0, 2.5, 6.0);
+    let mut found = tree.range_search_filter(&rect, |r| r.xmin >= 0.0);
+    assert_eq!(found.len(), 2);
+    found.sort_by(|a, b| a.avg_at(0).partial_cmp(&b.avg_at(0)).unwrap());
+    assert_approx(found[0], Rectangle::new(2.0, 3.0, 5.0, 6.0));
+    assert_approx(found[1], Rectangle::new(3.0, 7.0, 1.0, 3.0));
+    assert_eq!(
+        tree.range_search_filter(&rect, |_| true),
+        tree.range_search(&rect)
+    );
+    assert!(tree.range_search_filter(&rect, |r| r.xmin > 5.0).is_empty());
+}
+
+#[test]
+fn test_interval_overlap_extents() {
+    let a = Rectangle::new(0.0, 4.0, -3.0, 2.0);
+    let b = Rectangle::new(1.0, 4.0, 2.0, 6.0);
+    assert_eq!(a.overlap_extents(&b), Some([3.0, 0.0]));
+    assert_eq!(b.overlap_extents(&a), Some([3.0, 0.0]));
+    let c = Rectangle::new(-1.0, 2.5, -1.0, 1.0);
+    assert_eq!(a.overlap_extents(&c), Some([2.5, 2.0]));
+    assert_eq!(
+        a.overlap_extents(&c).map(|e| e.iter().product::<f64>()),
+        a.try_overlapping_volume(&c)
+    );
+    assert_eq!(b.overlap_extents(&c), None);
+}
+
+#[test]
+fn test_tree_chain() {
+    let mut rng = StdRng::seed_from_u64(18);
+    let first = IntervalTreeNode::from_intervals((0..100).map(|_| random_rect(&mut rng)).collect());
+    let second = basic_tree();
+    let chained = first.chain(&second);
+    assert_eq!(chained.len(), first.len() + second.len());
+    assert_eq!(chained.iter().count(), chained.len());
+    for _ in 0..20 {
+        let q = random_rect(&mut rng);
+        let mut expected = first.range_search(&q);
+        expected.extend(second.range_search(&q));
+        assert_eq!(chained.range_search(&q), expected);
+    }
+}
+
+#[test]
+fn test_tree_querying_array_point() {
+    let tree = basic_tree();
+    let intervals = sorted_by_x(tree.range_search(&[1.0, 2.0]));
+    assert_eq!(intervals, sorted_by_x(tree.range_search(&(1.0, 2.0))));
+    assert_eq!(intervals.len(), 3);
+    assert_approx(intervals[0], Rectangle::new(-5.0, 1.0, 2.0, 4.0));
+    assert!(intervals[0].contains_point(&[1.0, 2.0]));
+    assert!(tree.range_search(&[10.0, 10.0]).is_empty());
+}
+
+#[test]
+fn test_tree_querying_tuple_points() {
+    let segments = IntervalTreeNode::from_intervals(vec![0.0..1.0, 0.5..2.0, 3.0..4.0]);
+    assert_eq!(segments.range_search(&(0.75,)).len(), 2);
+    assert!(segments.range_search(&(2.5,)).is_empty());
+
+    let tree = basic_tree();
+    assert_eq!(tree.range_search(&(1.0, 2.0)).len(), 3);
+    assert_eq!(tree.range_search(&(6.0, 2.0)).len(), 1);
+
+    let dyn_intervals = dyn_tree_intervals();
+    let boxes = dyn_intervals
+        .iter()
+        .map(|i| BoxInterval([i.0[0], i.0[1], i.0[2]]))
+        .collect::<Vec<_>>();
+    let tree = IntervalTreeNode::from_intervals(boxes.clone());
+    let point = (0.6, 0.6, 0.3);
+    let expected = boxes.iter().filter(|b| b.contains_point(&point)).count();
+    assert!(expected > 0);
+    assert_eq!(tree.range_search(&point).len(), expected);
+    assert_eq!((1, 2, 3, 4).value(3), 4);
+}
+
+#[test]
+fn test_tree_bounding_box_pruning() {
+    let mut rng = StdRng::seed_from_u64(19);
+    let rectangles = (0..500).map(|_| random_rect(&mut rng)).collect::<Vec<_>>();
+    let tree = IntervalTreeNode::from_intervals(rectangles.clone());
+    for _ in 0..30 {
+        let q = random_rect(&mut rng);
+        let q = Rectangle::new(q.xmin * 1.5, q.xmax * 1.5, q.ymin * 1.5, q.ymax * 1.5);
+        let expected = rectangles
+            .iter()
+            .filter(|r| r.overlaps(&q))
+            .collect::<Vec<_>>();
+        assert_eq!(sorted_by_x(tree.range_search(&q)), sorted_by_x(expected));
+    }
+
+    // Overlaps with the split values on x, but not with any rectangle on y: only the bounding box
+    // of the root is read
+    let query = CountingQuery {
+        rect: Rectangle::new(-10.0, 10.0, 20.0, 30.0),
+        reads: std::cell::Cell::new(0),
+    };
+    assert!(tree.range_search(&query).is_empty());
+    assert!(query.reads.get() <= 4);
+    // Same for the other traversals
+    assert!(tree.intersecting(&query).next().is_none());
+    assert!(tree.range_search_strict(&query).is_empty());
+    assert!(query.reads.get() <= 12);
+}
+
+#[test]
+fn test_tree_range_search_copied() {
+    let boxes = basic_tree_rectangles()
+        .iter()
+        .map(|r| BoxInterval([(r.xmin, r.xmax), (r.ymin, r.ymax)]))
+        .collect::<Vec<_>>();
+    let tree = IntervalTreeNode::from_intervals(boxes);
+    for query in [(1.0, 2.0), (6.0, 2.0), (20.0, 20.0)] {
+        let copied = tree.range_search_copied(&query);
+        let referenced = tree.range_search(&query);
+        assert_eq!(copied.len(), referenced.len());
+        assert!(copied.iter().zip(referenced).all(|(c, r)| c == r));
+    }
+    assert_eq!(tree.range_search_copied(&(1.0, 2.0)).len(), 3);
+}
+
+#[test]
+fn test_tree_range_search_partitioned() {
+    let tree = basic_tree();
+    let rect = Rectangle::new(-4.0, 4.0, -4.5, 6.0);
+    let (contained, partial) = tree.range_search_partitioned(&rect);
+    let contained = sorted_by_x(contained);
+    let partial = sorted_by_x(partial);
+    assert_eq!(contained.len(), 3);
+    assert_approx(contained[0], Rectangle::new(-3.0, 2.0, -4.0, 2.0));
+    assert_approx(contained[1], Rectangle::new(0.0, 4.0, -3.0, 2.0));
+    assert_approx(contained[2], Rectangle::new(2.0, 3.0, 5.0, 6.0));
+    assert_eq!(partial.len(), 2);
+    assert_approx(partial[0], Rectangle::new(-5.0, 1.0, 2.0, 4.0));
+    assert_approx(partial[1], Rectangle::new(3.0, 7.0, 1.0, 3.0));
+
+    let mut all = contained;
+    all.extend(partial);
+    assert_eq!(sorted_by_x(all), sorted_by_x(tree.range_search(&rect)));
+}
+
+#[test]
+fn test_tree_leaf_slices() {
+    let mut rng = StdRng::seed_from_u64(20);
+    let mut tree =
+        IntervalTreeNode::from_intervals((0..300).map(|_| random_rect(&mut rng)).collect());
+    tree.retain(|r| r.xmin > -5.0);
+    assert!(tree.leaf_slices().all(|slice| !slice.is_empty()));
+    let flattened = tree.leaf_slices().flatten().collect::<Vec<_>>();
+    assert_eq!(flattened, tree.iter().collect::<Vec<_>>());
+    assert_eq!(basic_tree().leaf_slices().count(), 4);
+}
+
+#[cfg(feature = "rand")]
+#[test]
+fn test_tree_from_intervals_seeded() {
+    let mut rng = StdRng::seed_from_u64(21);
+    // Sorted input, with many ties on the averages
+    let mut rectangles = (0..400)
+        .map(|i| {
+            let x = (i / 20) as f64;
+            let w = rng.gen_range(0.0..5.0);
+            Rectangle::new(x - w, x + w, -w, w)
+        })
+        .collect::<Vec<_>>();
+    rectangles.sort_by(|a, b| a.avg_at(0).partial_cmp(&b.avg_at(0)).unwrap());
+    let default = IntervalTreeNode::from_intervals(rectangles.clone());
+    let seeded = IntervalTreeNode::from_intervals_seeded(rectangles.clone(), 7);
+    let again = IntervalTreeNode::from_intervals_seeded(rectangles.clone(), 7);
+    assert_eq!(
+        seeded.iter().collect::<Vec<_>>(),
+        again.iter().collect::<Vec<_>>()
+    );
+    assert!(seeded.height() <= default.height());
+    // Ties on x are left in traversal order by sorted_by_x
+    let key = |r: &&Rectangle| (r.xmin, r.xmax, r.ymin, r.ymax);
+    for _ in 0..30 {
+        let q = random_rect(&mut rng);
+        let mut found = seeded.range_search(&q);
+        let mut expected = default.range_search(&q);
+        found.sort_by(|a, b| key(a).partial_cmp(&key(b)).unwrap());
+        expected.sort_by(|a, b| key(a).partial_cmp(&key(b)).unwrap());
+        assert_eq!(found, expected);
+    }
+}
+
+#[test]
+fn test_tree_heterogeneous_intervals() {
+    let mut intervals: Vec<Box<dyn IntervalCore<2, Scalar = f64>>> = Vec::new();
+    for (i, r) in basic_tree_rectangles().into_iter().enumerate() {
+        if i % 2 == 0 {
+            intervals.push(Box::new(r));
+        } else {
+            intervals.push(Box::new(BoxInterval([(r.xmin, r.xmax), (r.ymin, r.ymax)])));
+        }
+    }
+    let tree = IntervalTreeNode::from_intervals(intervals);
+    let rect = Rectangle::new(1.0, 4.0, 2.5, 6.0);
+    let mut found = tree
+        .range_search(&rect)
+        .into_iter()
+        .map(|i| Rectangle::new(i.min_at(0), i.max_at(0), i.min_at(1), i.max_at(1)))
+        .collect::<Vec<_>>();
+    found.sort_by(|a, b| a.avg_at(0).partial_cmp(&b.avg_at(0)).unwrap());
+    assert_eq!(found.len(), 3);
+    assert_approx(&found[0], Rectangle::new(-5.0, 1.0, 2.0, 4.0));
+    assert_approx(&found[1], Rectangle::new(2.0, 3.0, 5.0, 6.0));
+    assert_approx(&found[2], Rectangle::new(3.0, 7.0, 1.0, 3.0));
+    assert_eq!(tree.range_search(&(1.0, 2.0)).len(), 3);
+}
+
+#[test]
+fn test_tree_boxed_periodic() {
+    let lon = |xmin, xmax| Periodic::new(Rectangle::new(xmin, xmax, -10.0, 10.0), 0);
+    let periodic = (0..24)
+        .map(|i| lon(-180.0 + i as f64 * 15.0, -170.0 + i as f64 * 15.0))
+        .chain([lon(170.0, -170.0), lon(100.0, -100.0)])
+        .collect::<Vec<_>>();
+    let mut intervals: Vec<Box<dyn IntervalCore<2, Scalar = f64>>> = Vec::new();
+    for p in &periodic {
+        intervals.push(Box::new(p.clone()));
+    }
+    let pacific = &intervals[24];
+    assert_eq!(pacific.cmp_at(0, 0.0), Ordering::Equal);
+    assert!(pacific.overlaps(&lon(178.0, 179.0)));
+    assert!(!pacific.overlaps(&lon(0.0, 10.0)));
+
+    let tree = IntervalTreeNode::from_intervals(intervals);
+    for q in [
+        lon(178.0, 179.0),
+        lon(-179.5, -179.0),
+        lon(175.0, -178.0),
+        lon(0.0, 10.0),
+    ] {
+        let expected = periodic.iter().filter(|p| p.overlaps(&q)).count();
+        assert_eq!(tree.range_search(&q).len(), expected);
+    }
+    assert_eq!(tree.range_search(&lon(178.0, 179.0)).len(), 2);
+}
+
+#[test]
+fn test_tree_scan_axis() {
+    let tree = basic_tree();
+    let expected = basic_tree_rectangles()
+        .into_iter()
+        .filter(|r| r.xmin <= 4.0 && 1.0 <= r.xmax)
+        .collect::<Vec<_>>();
+    assert_eq!(expected.len(), 5);
+    assert_eq!(
+        sorted_by_x(tree.scan_axis(0, 1.0, 4.0)),
+        sorted_by_x(expected.iter().collect())
+    );
+
+    let mut rng = StdRng::seed_from_u64(22);
+    let rectangles = (0..300).map(|_| random_rect(&mut rng)).collect::<Vec<_>>();
+    let tree = IntervalTreeNode::from_intervals(rectangles.clone());
+    for k in 0..2 {
+        let expected = rectangles
+            .iter()
+            .filter(|r| r.min_at(k) <= 4.0 && 1.0 <= r.max_at(k))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sorted_by_x(tree.scan_axis(k, 1.0, 4.0)),
+            sorted_by_x(expected)
+        );
+    }
+}
+
+#[test]
+fn test_tree_iter_order() {
+    // lt child of the root, then its center subtree (on y: its single leaf, then its gt child),
+    // then its gt child
+    let expected = [
+        Rectangle::new(-5.0, 1.0, 2.0, 4.0),
+        Rectangle::new(-3.0, 2.0, -4.0, 2.0),
+        Rectangle::new(0.0, 4.0, -3.0, 2.0),
+        Rectangle::new(2.0, 3.0, 5.0, 6.0),
+        Rectangle::new(3.0, 7.0, 1.0, 3.0),
+    ];
+    let tree = basic_tree();
+    assert_eq!(tree.iter().cloned().collect::<Vec<_>>(), expected);
+    assert_eq!(tree.iter().rev().cloned().collect::<Vec<_>>(), {
+        let mut reversed = expected.to_vec();
+        reversed.reverse();
+        reversed
+    });
+    let flattened = tree
+        .leaf_groups()
+        .into_iter()
+        .flatten()
+        .cloned()
+        .collect::<Vec<_>>();
+    assert_eq!(flattened, expected);
+}
+
+#[test]
+fn test_interval_clamp_bounds() {
+    let r = Rectangle::new(-5.0, 1.0, 2.0, 4.0);
+    let viewport = Rectangle::new(-2.0, 2.0, 0.0, 3.0);
+    assert_eq!(r.clamp_bounds(&viewport), Some([(-2.0, 1.0), (2.0, 3.0)]));
+    // Contained in the bounds: left untouched
+    let large = Rectangle::new(-10.0, 10.0, -10.0, 10.0);
+    assert_eq!(r.clamp_bounds(&large), Some([(-5.0, 1.0), (2.0, 4.0)]));
+    let disjoint = Rectangle::new(2.0, 3.0, 2.0, 4.0);
+    assert_eq!(r.clamp_bounds(&disjoint), None);
+}
+
+#[test]
+fn test_tree_memory_footprint() {
+    let tree = basic_tree();
+    let footprint = tree.memory_footprint();
+    let node_size = std::mem::size_of::<IntervalTreeNode<Rectangle, 2>>();
+    // At least the nodes and the intervals themselves, without much slack in the leaves
+    let minimum = tree.stats().nodes * node_size + tree.len() * std::mem::size_of::<Rectangle>();
+    assert!(footprint >= minimum);
+    assert!(footprint < 4 * minimum);
+
+    let mut rng = StdRng::seed_from_u64(23);
+    let small = IntervalTreeNode::from_intervals((0..100).map(|_| random_rect(&mut rng)).collect());
+    let large =
+        IntervalTreeNode::from_intervals((0..1000).map(|_| random_rect(&mut rng)).collect());
+    assert!(large.memory_footprint() > 5 * small.memory_footprint());
+    assert!(large.memory_footprint() < 20 * small.memory_footprint());
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn test_tree_from_interval_groups_par() {
+    let mut rng = StdRng::seed_from_u64(24);
+    let groups = (0..50)
+        .map(|i| {
+            (0..1 + i * 3)
+                .map(|_| random_rect(&mut rng))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let trees = IntervalTreeNode::from_interval_groups_par(groups.clone());
+    assert_eq!(trees.len(), groups.len());
+    for (tree, group) in trees.iter().zip(groups) {
+        let sequential = IntervalTreeNode::from_intervals(group);
+        assert_eq!(tree.to_structure(), sequential.to_structure());
+        assert_eq!(
+            tree.iter().collect::<Vec<_>>(),
+            sequential.iter().collect::<Vec<_>>()
+        );
+    }
+}
+
+#[test]
+fn test_tree_subtree_for() {
+    let mut rng = StdRng::seed_from_u64(25);
+    let tree = IntervalTreeNode::from_intervals((0..500).map(|_| random_rect(&mut rng)).collect());
+    let region = Rectangle::new(-2.0, 3.0, -1.0, 4.0);
+    let subtree = tree.subtree_for(&region).unwrap();
+    assert!(subtree.len() < tree.len());
+    assert_eq!(subtree.len(), tree.range_search(&region).len());
+    for _ in 0..20 {
+        let (x, y) = (rng.gen_range(-2.0..2.0), rng.gen_range(-1.0..3.0));
+        let q = Rectangle::new(x, x + 1.0, y, y + rng.gen_range(0.0..1.0));
+        let found = subtree
+            .range_search(&q)
+            .into_iter()
+            .map(|r| r.get())
+            .collect();
+        assert_eq!(sorted_by_x(found), sorted_by_x(tree.range_search(&q)));
+    }
+    assert!(tree
+        .subtree_for(&Rectangle::new(50.0, 60.0, 50.0, 60.0))
+        .is_none());
+}
+
+#[test]
+fn test_tree_refresh_splits() {
+    let mut rng = StdRng::seed_from_u64(26);
+    let rectangles = (0..2000).map(|_| random_rect(&mut rng)).collect::<Vec<_>>();
+    let mut tree = IntervalTreeNode::from_intervals(rectangles.clone());
+    // Most of the remaining intervals end up in the gt child of the root
+    tree.retain(|r| r.xmin > 2.0);
+    let remaining = tree.len();
+    let before = tree.stats();
+    let gt_share = |t: &IntervalTreeNode<Rectangle, 2>| {
+        t.cursor().go_right().map_or(0, |c| c.node().len()) as f64 / t.len() as f64
+    };
+    assert!(gt_share(&tree) > 0.9);
+
+    tree.refresh_splits();
+    assert_eq!(tree.len(), remaining);
+    assert!(gt_share(&tree) <= 0.5);
+    // The empty nodes left by retain are dropped
+    assert!(tree.stats().nodes < before.nodes);
+    assert_eq!(tree.validate_invariants(), Ok(()));
+
+    let kept = rectangles
+        .iter()
+        .filter(|r| r.xmin > 2.0)
+        .collect::<Vec<_>>();
+    for _ in 0..30 {
+        let q = random_rect(&mut rng);
+        let expected = kept
+            .iter()
+            .copied()
+            .filter(|r| r.overlaps(&q))
+            .collect::<Vec<_>>();
+        assert_eq!(sorted_by_x(tree.range_search(&q)), sorted_by_x(expected));
+    }
+
+    // The split dimensions of the tree are kept
+    let mut tree = IntervalTreeNode::from_intervals_order(rectangles, [1, 0]);
+    tree.retain(|r| r.ymin > 2.0);
+    tree.refresh_splits();
+    assert_eq!(tree.cursor().split_dimension(), 1);
+    assert_eq!(tree.validate_invariants(), Ok(()));
+    let gt_share =
+        tree.cursor().go_right().map_or(0, |c| c.node().len()) as f64 / tree.len() as f64;
+    assert!(gt_share <= 0.5);
+}
+
+#[test]
+fn test_tree_range_search_by_depth() {
+    let tree = basic_tree();
+    let height = tree.height();
+    for q in [
+        Rectangle::new(-1.0, 1.0, -1.0, 1.0),
+        Rectangle::new(2.0, 8.0, 3.0, 4.0),
+        Rectangle::new(-100.0, 100.0, -100.0, 100.0),
+    ] {
+        let by_depth = tree.range_search_by_depth(&q);
+        assert!(by_depth.iter().all(|&(depth, _)| depth < height));
+        let found = by_depth.into_iter().map(|(_, r)| r).collect::<Vec<_>>();
+        assert_eq!(found, tree.range_search(&q));
+    }
+
+    // The whole tree: the matches come from below the root, down to the deepest leaf
+    let all = tree.range_search_by_depth(&Rectangle::new(-100.0, 100.0, -100.0, 100.0));
+    assert_eq!(all.len(), tree.len());
+    assert!(all.iter().any(|&(depth, _)| depth > 0));
+    assert_eq!(all.iter().map(|&(depth, _)| depth).max(), Some(height - 1));
+}
+
+#[test]
+fn test_tree_range_search_iou() {
+    let detection = Rectangle::new(0.0, 4.0, 0.0, 4.0);
+    let boxes = vec![
+        // Identical: IoU 1
+        Rectangle::new(0.0, 4.0, 0.0, 4.0),
+        // Half of the detection: IoU 0.5
+        Rectangle::new(0.0, 2.0, 0.0, 4.0),
+        // Overlap of 8 for a union of 24: IoU 1/3
+        Rectangle::new(2.0, 6.0, 0.0, 4.0),
+        // Overlap of 4 for a union of 28: IoU 1/7
+        Rectangle::new(2.0, 6.0, 2.0, 6.0),
+        // No overlap
+        Rectangle::new(10.0, 12.0, 0.0, 4.0),
+    ];
+    let tree = IntervalTreeNode::from_intervals(boxes.clone());
+
+    let at_03 = sorted_by_x(tree.range_search_iou(&detection, 0.3));
+    assert_eq!(at_03, sorted_by_x(boxes[..3].iter().collect()));
+    let at_06 = tree.range_search_iou(&detection, 0.6);
+    assert_eq!(at_06, vec![&boxes[0]]);
+}
+
+#[test]
+fn test_tree_range_search_eps() {
+    let a = Rectangle::new(0.0, 1.0, 0.0, 1.0);
+    let b = Rectangle::new(1.0 + 1e-9, 2.0, 0.0, 1.0);
+    assert!(!a.overlaps_within(&b, 0.0));
+    assert!(a.overlaps_within(&b, 1e-6));
+    assert!(b.overlaps_within(&a, 1e-6));
+    assert!(!a.overlaps_within(&Rectangle::new(1.0, 2.0, 1.0 + 1e-5, 2.0), 1e-6));
+
+    let mut rectangles = basic_tree_rectangles();
+    rectangles.push(b.clone());
+    let tree = IntervalTreeNode::from_intervals(rectangles);
+    assert!(!tree.range_search_eps(&a, 0.0).contains(&&b));
+    assert!(tree.range_search_eps(&a, 1e-6).contains(&&b));
+    assert_eq!(
+        sorted_by_x(tree.range_search_eps(&a, 0.0)),
+        sorted_by_x(tree.range_search(&a))
+    );
+}
+
+#[test]
+fn test_tree_explain_query() {
+    // Crossing bars, sharing the center of the root on both dimensions
+    let vertical = Rectangle::new(1.0, 2.0, 0.0, 3.0);
+    let horizontal = Rectangle::new(0.0, 3.0, 1.0, 2.0);
+    let tree = IntervalTreeNode::from_intervals(vec![vertical.clone(), horizontal.clone()]);
+    // In a corner of the bounding box of the root, missing vertical on x, and horizontal on y
+    // only
+    let explanation = tree.explain_query(&Rectangle::new(2.5, 3.0, 2.5, 3.0));
+    assert!(explanation.matches.is_empty());
+    let mut near_misses = explanation.near_misses;
+    near_misses.sort_by_key(|(_, k)| *k);
+    assert_eq!(near_misses, vec![(&vertical, 0), (&horizontal, 1)]);
+
+    let tree = basic_tree();
+    let q = Rectangle::new(1.0, 4.0, 2.5, 6.0);
+    let explanation = tree.explain_query(&q);
+    assert_eq!(explanation.matches, tree.range_search(&q));
+    for (r, k) in explanation.near_misses {
+        assert!((0..k).all(|j| r.overlaps_at(j, &q)));
+        assert!(!r.overlaps_at(k, &q));
+    }
+}
+
+#[test]
+fn test_tree_from_sorted_by_dimension() {
+    let mut rng = StdRng::seed_from_u64(27);
+    let rectangles = (0..1000).map(|_| random_rect(&mut rng)).collect::<Vec<_>>();
+    let orders: [Vec<usize>; 2] = std::array::from_fn(|k| {
+        let mut order = (0..rectangles.len()).collect::<Vec<_>>();
+        order.sort_by(|&a, &b| {
+            rectangles[a]
+                .avg_at(k)
+                .partial_cmp(&rectangles[b].avg_at(k))
+                .unwrap()
+        });
+        order
+    });
+
+    let expected = IntervalTreeNode::from_intervals(rectangles.clone());
+    let tree = IntervalTreeNode::from_sorted_by_dimension(rectangles, orders);
+    assert_eq!(tree.validate_invariants(), Ok(()));
+    assert_eq!(tree.stats(), expected.stats());
+    assert!(tree.iter().eq(expected.iter()));
+    for _ in 0..30 {
+        let q = random_rect(&mut rng);
+        assert_eq!(tree.range_search(&q), expected.range_search(&q));
+    }
+}
+
+#[test]
+#[should_panic(expected = "orders[0] should be a permutation of the intervals!")]
+fn test_tree_from_sorted_by_dimension_invalid_order() {
+    let rectangles = basic_tree_rectangles();
+    let n = rectangles.len();
+    // Checked in release builds as well
+    IntervalTreeNode::from_sorted_by_dimension(rectangles, [vec![0; n], (0..n).collect()]);
+}
+
+#[test]
+fn test_tree_range_search_cancellable() {
+    let tree = basic_tree();
+    let q = Rectangle::new(1.0, 4.0, 2.5, 6.0);
+    let cancel = AtomicBool::new(false);
+    assert_eq!(
+        tree.range_search_cancellable(&q, &cancel),
+        Some(tree.range_search(&q))
+    );
+    cancel.store(true, AtomicOrdering::Relaxed);
+    assert_eq!(tree.range_search_cancellable(&q, &cancel), None);
+}
+
+#[test]
+fn test_interval_project() {
+    let rect = Rectangle::new(0.0, 4.0, -3.0, 2.0);
+    assert_eq!(rect.project([0]), [(0.0, 4.0)]);
+    assert_eq!(rect.project([1, 0]), [(-3.0, 2.0), (0.0, 4.0)]);
+
+    // Projecting the rectangles on x gives a 1D tree
+    let rectangles = basic_tree_rectangles();
+    let projections = rectangles
+        .iter()
+        .map(|r| ProjectedInterval::from(r.project([0])))
+        .collect::<Vec<_>>();
+    let tree = IntervalTreeNode::<_, 1>::from_intervals(projections);
+    let mut found = tree
+        .range_search(&BoxInterval([(1.0, 4.0)]))
+        .into_iter()
+        .map(|p| p.0[0])
+        .collect::<Vec<_>>();
+    found.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let mut expected = rectangles
+        .iter()
+        .filter(|r| r.xmin <= 4.0 && 1.0 <= r.xmax)
+        .map(|r| (r.xmin, r.xmax))
+        .collect::<Vec<_>>();
+    expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    assert!(!expected.is_empty());
+    assert_eq!(found, expected);
+}
+
+#[test]
+fn test_tree_query_heatmap() {
+    let mut rng = StdRng::seed_from_u64(28);
+    let mut rectangles = (0..300).map(|_| random_rect(&mut rng)).collect::<Vec<_>>();
+    // Far away from the random rectangles: the only match of the query below
+    let target = Rectangle::new(1000.0, 1001.0, 1000.0, 1001.0);
+    rectangles.push(target.clone());
+    let tree = IntervalTreeNode::from_intervals(rectangles);
+
+    let queries = [Rectangle::new(1000.2, 1000.3, 1000.2, 1000.3)];
+    let report = tree.query_heatmap(&queries);
+    assert_eq!(report.nodes[&Vec::new()].visits, 1);
+    let hot = report
+        .nodes
+        .iter()
+        .filter(|(_, heat)| heat.matches > 0)
+        .collect::<Vec<_>>();
+    assert_eq!(hot.len(), 1);
+    let (path, heat) = hot[0];
+    assert_eq!(heat.matches, 1);
+
+    // Following the path leads to the leaf holding the target
+    let mut cursor = tree.cursor();
+    for step in path {
+        cursor = match step {
+            PathStep::Lt => cursor.go_left(),
+            PathStep::Gt => cursor.go_right(),
+            PathStep::Center => cursor.go_center().ok(),
+        }
+        .unwrap();
+    }
+    assert!(cursor.leaf_intervals().unwrap().contains(&target));
+
+    // Queries covering the whole tree visit every node, and match every interval once
+    let all = tree.query_heatmap(&vec![Rectangle::new(-2000.0, 2000.0, -2000.0, 2000.0); 2]);
+    assert_eq!(
+        all.nodes.values().map(|h| h.matches).sum::<usize>(),
+        2 * tree.len()
+    );
+    assert!(all.nodes.values().all(|h| h.visits == 2));
+}
+
+#[cfg(feature = "geo")]
+#[test]
+fn test_tree_geo_rects() {
+    use geo_types::{Coord, Rect};
+
+    let rectangles = basic_tree_rectangles();
+    let rects = rectangles
+        .iter()
+        .map(|r| {
+            Rect::new(
+                Coord {
+                    x: r.xmin,
+                    y: r.ymin,
+                },
+                Coord {
+                    x: r.xmax,
+                    y: r.ymax,
+                },
+            )
+        })
+        .collect::<Vec<_>>();
+    let tree = IntervalTreeNode::from_intervals(rects.clone());
+    let expected = basic_tree();
+    for (x, y) in [(0.5, 0.5), (3.0, 4.0), (3.5, 1.5)] {
+        let found = tree.range_search(&Coord { x, y });
+        let mut found = found
+            .into_iter()
+            .map(|r| (r.min().x, r.max().x, r.min().y, r.max().y))
+            .collect::<Vec<_>>();
+        found.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let mut expected = expected
+            .range_search(&(x, y))
+            .into_iter()
+            .map(|r| (r.xmin, r.xmax, r.ymin, r.ymax))
+            .collect::<Vec<_>>();
+        expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(found, expected);
+    }
+    assert_eq!(tree.range_search(&Coord { x: 0.5, y: 0.5 }).len(), 2);
+}
+
+#[test]
+fn test_tree_from_intervals_unchecked() {
+    let mut rng = StdRng::seed_from_u64(29);
+    let rectangles = (0..1000).map(|_| random_rect(&mut rng)).collect::<Vec<_>>();
+    let tree = IntervalTreeNode::from_intervals_unchecked(rectangles.clone());
+    let expected = IntervalTreeNode::from_intervals(rectangles);
+    assert_eq!(tree.to_structure(), expected.to_structure());
+    assert!(tree.iter().eq(expected.iter()));
+    assert_eq!(tree.validate_invariants(), Ok(()));
+}
+
+#[test]
+fn test_tree_range_search_convert() {
+    let tree = basic_tree();
+    let q = BoxInterval([(1.0f32, 4.0f32), (2.5f32, 6.0f32)]);
+    let found = tree.range_search_convert(&q, |v| v as f64);
+    assert_eq!(found.len(), 3);
+    assert_eq!(
+        found,
+        tree.range_search(&Rectangle::new(1.0, 4.0, 2.5, 6.0))
+    );
+}
+
+#[test]
+fn test_tree_range_search_split() {
+    let crossing = Rectangle::new(-2.0, 2.0, 0.0, 1.0);
+    let inner = Rectangle::new(0.5, 1.0, 0.2, 0.8);
+    let tree = IntervalTreeNode::from_intervals(vec![crossing.clone(), inner.clone()]);
+    let q = Rectangle::new(0.0, 5.0, -1.0, 0.5);
+    let mut results = tree.range_search_split(&q);
+    results.sort_by(|a, b| a.interval.xmin.partial_cmp(&b.interval.xmin).unwrap());
+    assert_eq!(results.len(), 2);
+
+    // Crosses the left edge and the top edge of the query
+    assert_eq!(results[0].interval, &crossing);
+    assert_eq!(results[0].inside, [(0.0, 2.0), (0.0, 0.5)]);
+    assert_eq!(
+        results[0].outside,
+        vec![[(-2.0, 0.0), (0.0, 1.0)], [(0.0, 2.0), (0.5, 1.0)]]
+    );
+    // The portions cover the whole interval
+    let volume = |b: &[(f64, f64); 2]| BoxInterval(*b).volume();
+    let total = volume(&results[0].inside) + results[0].outside.iter().map(volume).sum::<f64>();
+    assert_eq!(total, crossing.volume());
+
+    assert_eq!(results[1].interval, &inner);
+    assert_eq!(results[1].inside, [(0.5, 1.0), (0.2, 0.5)]);
+    assert_eq!(results[1].outside, vec![[(0.5, 1.0), (0.5, 0.8)]]);
+}
+
+#[test]
+fn test_managed_tree_deferred() {
+    let mut rng = StdRng::seed_from_u64(30);
+    let mut rectangles = (0..100).map(|_| random_rect(&mut rng)).collect::<Vec<_>>();
+    let mut managed = ManagedIntervalTree::deferred(rectangles.clone());
+    assert!(!managed.is_dirty());
+
+    for _ in 0..50 {
+        let r = random_rect(&mut rng);
+        rectangles.push(r.clone());
+        managed.insert(r);
+    }
+    let removed = rectangles.swap_remove(3);
+    assert_eq!(managed.remove(&removed), Some(removed));
+    assert!(managed.is_dirty());
+    assert_eq!(managed.rebalances(), 0);
+    assert_eq!(managed.len(), rectangles.len());
+
+    let q = Rectangle::new(-2.0, 3.0, -1.0, 4.0);
+    let expected = rectangles
+        .iter()
+        .filter(|r| r.overlaps(&q))
+        .collect::<Vec<_>>();
+    // The first query flushes the pending mutations, rebuilding the tree once
+    assert_eq!(
+        sorted_by_x(managed.range_search(&q)),
+        sorted_by_x(expected.clone())
+    );
+    assert_eq!(managed.rebalances(), 1);
+    assert!(!managed.is_dirty());
+    assert_eq!(managed.tree().unwrap().len(), rectangles.len());
+    assert_eq!(
+        sorted_by_x(managed.range_search(&q)),
+        sorted_by_x(expected.clone())
+    );
+    assert_eq!(managed.rebalances(), 1);
+}
+
+#[test]
+fn test_interval_is_degenerate() {
+    let rect = Rectangle::new(0.0, 4.0, -3.0, 2.0);
+    assert!(!rect.is_degenerate_at(0) && !rect.is_degenerate_at(1));
+    assert!(!rect.is_point());
+
+    let segment = Rectangle::new(1.0, 1.0, -3.0, 2.0);
+    assert!(segment.is_degenerate_at(0));
+    assert!(!segment.is_degenerate_at(1));
+    assert!(!segment.is_point());
+
+    let point = Rectangle::new(1.0, 1.0, 2.0, 2.0);
+    assert!(point.is_degenerate_at(0) && point.is_degenerate_at(1));
+    assert!(point.is_point());
+    assert!((1.0, 2.0).is_point());
+}
+
+#[test]
+fn test_tree_remove_handle() {
+    let mut rng = StdRng::seed_from_u64(31);
+    let rectangles = (0..500).map(|_| random_rect(&mut rng)).collect::<Vec<_>>();
+    let mut tree = IntervalTreeNode::from_intervals(rectangles.clone());
+    let q = Rectangle::new(-2.0, 3.0, -1.0, 4.0);
+
+    let handles = tree.range_search_handles(&q);
+    let found = handles.iter().map(|(_, r)| *r).collect::<Vec<_>>();
+    assert_eq!(found, tree.range_search(&q));
+    let found = found.len();
+    let (handle, target) = handles
+        .iter()
+        .max_by_key(|(handle, _)| handle.path().len())
+        .unwrap()
+        .clone();
+    let target = target.clone();
+    assert!(!handle.path().is_empty());
+
+    assert_eq!(tree.remove_handle(&handle).as_ref(), Some(&target));
+    assert_eq!(tree.len(), rectangles.len() - 1);
+    assert!(!tree.range_search(&q).contains(&&target));
+    assert_eq!(tree.range_search(&q).len(), found - 1);
+
+    // The path of the handle does not exist in a smaller tree
+    assert!(handle.path().len() >= basic_tree().height());
+    assert_eq!(basic_tree().remove_handle(&handle), None);
+}
+
+#[test]
+fn test_interval_centroid_distance() {
+    // Centered on (1, 1)
+    let q = Rectangle::new(0.0, 2.0, 0.0, 2.0);
+    let rectangles = [
+        Rectangle::new(-1.0, 3.0, -1.0, 3.0),
+        Rectangle::new(1.0, 3.0, 0.0, 2.0),
+        Rectangle::new(2.0, 4.0, 3.0, 5.0),
+    ];
+    let distances = rectangles
+        .iter()
+        .map(|r| r.centroid_distance_sq(&q))
+        .collect::<Vec<_>>();
+    assert_eq!(distances, vec![0.0, 1.0, 13.0]);
+    assert_eq!(q.centroid_distance_sq(&rectangles[2]), 13.0);
+    assert_eq!(
+        BoxInterval([(0u32, 2), (0, 2)]).centroid_distance_sq(&BoxInterval([(4, 6), (1, 3)])),
+        17
+    );
+}
+
+#[test]
+fn test_tree_builder() {
+    let mut builder = IntervalTreeBuilder::new();
+    assert!(builder.is_empty());
+    let rectangles = basic_tree_rectangles();
+    builder.push(rectangles[0].clone());
+    builder.push(rectangles[1].clone());
+    builder.extend(rectangles[2..].iter().cloned());
+    assert_eq!(builder.len(), 5);
+
+    let tree = builder.finalize().unwrap();
+    let expected = basic_tree();
+    assert_eq!(tree.to_structure(), expected.to_structure());
+    assert!(tree.iter().eq(expected.iter()));
+
+    assert!(IntervalTreeBuilder::<Rectangle, 2>::new()
+        .finalize()
+        .is_none());
+}
+
+#[test]
+fn test_tree_range_search_mask() {
+    let mut rng = StdRng::seed_from_u64(32);
+    let rectangles = (0..300).map(|_| random_rect(&mut rng)).collect::<Vec<_>>();
+    let tree = IntervalTreeNode::from_intervals_indexed(rectangles.clone());
+    let q = Rectangle::new(-2.0, 3.0, -1.0, 4.0);
+    let mask = tree.range_search_mask(&q);
+    assert_eq!(mask.len(), rectangles.len());
+
+    let mut matched = tree
+        .range_search(&q)
+        .into_iter()
+        .map(|i| i.index())
+        .collect::<Vec<_>>();
+    matched.sort();
+    let positions = (0..mask.len()).filter(|&i| mask[i]).collect::<Vec<_>>();
+    assert!(!positions.is_empty());
+    assert_eq!(positions, matched);
+    assert!(positions.iter().all(|&i| rectangles[i].overlaps(&q)));
+
+    // Removing an interval does not change the length of the masks
+    let mut tree = tree;
+    let removed = positions[0];
+    tree.retain(|i| i.index() != removed);
+    let mask = tree.range_search_mask(&q);
+    assert_eq!(mask.len(), rectangles.len());
+    assert!(!mask[removed]);
+    assert_eq!(mask.iter().filter(|&&m| m).count(), positions.len() - 1);
+    let far = Rectangle::new(1000.0, 1001.0, 1000.0, 1001.0);
+    assert_eq!(tree.range_search_mask(&far), vec![false; rectangles.len()]);
+    // Nor does removing the last intervals of the input
+    tree.retain(|i| i.index() + 10 < rectangles.len());
+    assert_eq!(tree.input_len(), rectangles.len());
+    assert_eq!(tree.range_search_mask(&q).len(), rectangles.len());
+}
+
+#[test]
+fn test_managed_tree_first_inserts() {
+    let mut managed = ManagedIntervalTree::new(Vec::new());
+    managed.insert(Rectangle::new(0.0, 1.0, 0.0, 1.0));
+    assert_eq!(managed.len(), 1);
+    assert_eq!(managed.tree().unwrap().len(), 1);
+    managed.insert(Rectangle::new(2.0, 3.0, 0.0, 1.0));
+    assert_eq!(managed.len(), 2);
+    assert_eq!(managed.tree().unwrap().len(), 2);
+    assert_eq!(managed.rebalances(), 0);
+}
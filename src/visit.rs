@@ -0,0 +1,116 @@
+use crate::box_interval::PairBounds;
+use crate::{Interval, IntervalTreeNode, LeafIntervals, NodeContent, PathStep};
+use std::cmp::Ordering;
+use std::convert::Infallible;
+use std::ops::ControlFlow;
+
+/// Callbacks of IntervalTreeNode::visit, which walks the nodes a query reaches.
+pub(crate) trait Visitor<'a, I: 'a, const K: usize> {
+    type Break;
+
+    /// Called on every node reaching the query, before its leaf or subtree is visited.
+    fn node(&mut self) -> ControlFlow<Self::Break> {
+        ControlFlow::Continue(())
+    }
+
+    /// Called on the leaf of every node reaching the query. The intervals still have to be tested
+    /// against it.
+    fn leaf(&mut self, intervals: &'a LeafIntervals<I>) -> ControlFlow<Self::Break>;
+
+    /// Called when descending to a child (or the center subtree) of the current node.
+    fn descend(&mut self, _step: PathStep) {}
+
+    /// Called when going back up from the node the last descend went to.
+    fn ascend(&mut self) {}
+}
+
+/// Visitor only called on the leaves, for the traversals which do not need the other callbacks.
+pub(crate) struct Leaves<F>(pub(crate) F);
+
+impl<'a, I: 'a, const K: usize, B, F> Visitor<'a, I, K> for Leaves<F>
+where
+    F: FnMut(&'a LeafIntervals<I>) -> ControlFlow<B>,
+{
+    type Break = B;
+    fn leaf(&mut self, intervals: &'a LeafIntervals<I>) -> ControlFlow<B> {
+        (self.0)(intervals)
+    }
+}
+
+impl<const K: usize, I: Interval<K>> IntervalTreeNode<I, K> {
+    /// Walks the nodes which may hold intervals overlapping with x (pruning the others on their
+    /// bounding box and split value), in the order of range_search: the center first, then
+    /// lt_nodes and gt_nodes.
+    /// All the range searches go through this (or reaches_bbox and reaches for the ones walking
+    /// the tree on their own), so that they share the same pruning.
+    pub(crate) fn visit<'a, II, V>(&'a self, x: &II, visitor: &mut V) -> ControlFlow<V::Break>
+    where
+        II: Interval<K, Scalar = I::Scalar>,
+        V: Visitor<'a, I, K>,
+    {
+        if !self.reaches_bbox(x) {
+            return ControlFlow::Continue(());
+        }
+        visitor.node()?;
+        match &self.center {
+            NodeContent::Subtree(n) => {
+                visitor.descend(PathStep::Center);
+                n.visit(x, visitor)?;
+                visitor.ascend();
+            }
+            NodeContent::Leaf(intervals) => visitor.leaf(intervals)?,
+        }
+        for (step, child) in [
+            (PathStep::Lt, &self.lt_nodes),
+            (PathStep::Gt, &self.gt_nodes),
+        ] {
+            if let Some(n) = child {
+                if self.reaches(x, step) {
+                    visitor.descend(step);
+                    n.visit(x, visitor)?;
+                    visitor.ascend();
+                }
+            }
+        }
+        ControlFlow::Continue(())
+    }
+
+    /// Same as visit, calling f on the leaves only, for the traversals which are never interrupted.
+    pub(crate) fn visit_leaves<'a, II, F>(&'a self, x: &II, mut f: F)
+    where
+        II: Interval<K, Scalar = I::Scalar>,
+        F: FnMut(&'a LeafIntervals<I>),
+    {
+        let _ = self.visit(
+            x,
+            &mut Leaves(|intervals| {
+                f(intervals);
+                ControlFlow::<Infallible>::Continue(())
+            }),
+        );
+    }
+
+    /// Whether x overlaps with the bounding box of the node, i.e. may overlap with some of its
+    /// intervals (always true for the nodes without bounding box).
+    pub(crate) fn reaches_bbox<II: Interval<K, Scalar = I::Scalar>>(&self, x: &II) -> bool {
+        self.bbox.as_ref().is_none_or(|bbox| {
+            let bbox = PairBounds(bbox);
+            (0..bbox.0.len()).all(|k| x.overlaps_at(k, &bbox))
+        })
+    }
+
+    /// Whether x may overlap with the intervals of the given child of the node, as far as its
+    /// split value tells: lt_nodes only hold intervals ending before center_val, and gt_nodes
+    /// intervals starting after it.
+    pub(crate) fn reaches<II: Interval<K, Scalar = I::Scalar>>(
+        &self,
+        x: &II,
+        step: PathStep,
+    ) -> bool {
+        match step {
+            PathStep::Lt => x.cmp_at(self.k, self.center_val) != Ordering::Greater,
+            PathStep::Center => true,
+            PathStep::Gt => x.cmp_at(self.k, self.center_val) != Ordering::Less,
+        }
+    }
+}